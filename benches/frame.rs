@@ -0,0 +1,30 @@
+use std::time::Instant;
+
+use rust_nes::nes::NES;
+
+const WARMUP_FRAMES: u32 = 60;
+const FRAMES: u32 = 600;
+
+fn bench(name: &str, rom_data: &[u8]) {
+    let mut nes = NES::new(rom_data);
+    for _ in 0..WARMUP_FRAMES {
+        nes.frame();
+    }
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        nes.frame();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{}: {} frames in {:.3}s ({:.1} fps)",
+        name,
+        FRAMES,
+        elapsed.as_secs_f64(),
+        FRAMES as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    bench("SHOOT", include_bytes!("../static/nes/SHOOT.nes"));
+    bench("MapWalker", include_bytes!("../static/nes/MapWalker.nes"));
+}
@@ -0,0 +1,22 @@
+use std::{env, fs, process};
+
+use rust_nes::{debugger::Debugger, nes::NES, remote::Server};
+
+// cargo run --example debug_server -- <rom.nes> [port]
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+    let Some(path) = args.get(1) else {
+        eprintln!("usage: debug_server <rom.nes> [port]");
+        process::exit(1);
+    };
+    let port = args.get(2).map_or(6502, |port| port.parse().unwrap());
+    let rom_data = fs::read(path).unwrap();
+    let mut debugger = Debugger::new(NES::new(&rom_data));
+    let server = Server::bind(port).unwrap();
+    println!("listening on {}", server.local_addr().unwrap());
+    loop {
+        if let Err(error) = server.serve(&mut debugger) {
+            eprintln!("client error: {}", error);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::rom::ROM;
+use crate::{region::Region, rom::ROM};
 
 const HEADER_SIZE: usize = 0x0010;
 const PROGRAM_ROM_UNIT_SIZE: usize = 0x4000; // 16KB
@@ -8,6 +8,7 @@ pub struct Cartridge {
     pub program_rom: ROM,
     pub character_rom: ROM,
     pub is_horizontal_mirroring: bool,
+    pub region: Region,
 }
 
 impl Cartridge {
@@ -15,6 +16,7 @@ impl Cartridge {
         let program_rom_size = data[4] as usize * PROGRAM_ROM_UNIT_SIZE;
         let character_rom_size = data[5] as usize * CHARACTER_ROM_UNIT_SIZE;
         let is_horizontal_mirroring = data[6] & 0b0000_0001 == 0;
+        let region = Self::region(data);
 
         let program_rom_start = HEADER_SIZE;
         let program_rom_end = program_rom_start + program_rom_size;
@@ -30,6 +32,19 @@ impl Cartridge {
             program_rom,
             character_rom,
             is_horizontal_mirroring,
+            region,
+        }
+    }
+    // Only NES 2.0 headers carry a reliable timing field.
+    fn region(data: &[u8]) -> Region {
+        let is_nes2 = data[7] & 0b0000_1100 == 0b0000_1000;
+        if !is_nes2 {
+            return Region::NTSC;
+        }
+        match data[12] & 0b11 {
+            1 => Region::PAL,
+            3 => Region::Dendy,
+            _ => Region::NTSC,
         }
     }
 }
@@ -62,5 +77,19 @@ mod tests {
         data[6] = 0x00;
         let cartridge = Cartridge::new(&data);
         assert_eq!(cartridge.is_horizontal_mirroring, true);
+        assert_eq!(cartridge.region, Region::NTSC);
+
+        // NES 2.0 timing field
+        data[7] = 0x08;
+        data[12] = 0x01;
+        assert_eq!(Cartridge::new(&data).region, Region::PAL);
+        data[12] = 0x03;
+        assert_eq!(Cartridge::new(&data).region, Region::Dendy);
+        data[12] = 0x02;
+        assert_eq!(Cartridge::new(&data).region, Region::NTSC);
+        // ignored without the NES 2.0 identifier
+        data[7] = 0x00;
+        data[12] = 0x01;
+        assert_eq!(Cartridge::new(&data).region, Region::NTSC);
     }
 }
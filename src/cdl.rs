@@ -0,0 +1,169 @@
+use crate::{Byte, Word};
+
+// PRG flags, laid out like FCEUX and Mesen .cdl files
+pub const CODE: Byte = 0x01;
+pub const DATA: Byte = 0x02;
+// the 8K CPU window ($8000, $A000, $C000, $E000) of the last access
+const WINDOW_MASK: Byte = 0x0C;
+pub const INDIRECT_CODE: Byte = 0x10;
+pub const INDIRECT_DATA: Byte = 0x20;
+// DMC sample bytes, kept on import; samples are not fetched yet
+pub const PCM_AUDIO: Byte = 0x40;
+
+// CHR flags
+pub const RENDERED: Byte = 0x01;
+pub const READ: Byte = 0x02;
+
+// What every PRG and CHR byte has been used for. CHR RAM is not logged, so
+// the file is only the PRG part for those carts, as in FCEUX.
+pub struct CodeDataLog {
+    program: Vec<Byte>,
+    character: Vec<Byte>,
+}
+
+impl CodeDataLog {
+    pub fn new(program_rom_size: usize, character_rom_size: usize) -> Self {
+        CodeDataLog {
+            program: vec![0; program_rom_size],
+            character: vec![0; character_rom_size],
+        }
+    }
+    // Takes a .cdl file made for a ROM with the same sizes.
+    pub fn from_bytes(
+        data: &[u8],
+        program_rom_size: usize,
+        character_rom_size: usize,
+    ) -> Result<Self, String> {
+        if data.len() != program_rom_size + character_rom_size {
+            return Err(format!(
+                "CDL size {} does not match PRG {} + CHR {}",
+                data.len(),
+                program_rom_size,
+                character_rom_size
+            ));
+        }
+        let (program, character) = data.split_at(program_rom_size);
+        Ok(CodeDataLog {
+            program: program.to_vec(),
+            character: character.to_vec(),
+        })
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.program[..], &self.character[..]].concat()
+    }
+
+    pub fn program(&self) -> &[Byte] {
+        &self.program
+    }
+    pub fn character(&self) -> &[Byte] {
+        &self.character
+    }
+    pub fn mark_program(&mut self, offset: usize, address: Word, flags: Byte) -> () {
+        if let Some(entry) = self.program.get_mut(offset) {
+            let window = ((address >> 13) as Byte & 0x03) << 2;
+            *entry = (*entry & !WINDOW_MASK) | flags | window;
+        }
+    }
+    pub fn mark_character(&mut self, offset: usize, flags: Byte) -> () {
+        if let Some(entry) = self.character.get_mut(offset) {
+            *entry |= flags;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::NES;
+
+    #[test]
+    fn test_mark() {
+        let mut log = CodeDataLog::new(0x4000, 0x2000);
+        log.mark_program(0x0000, 0xC000, CODE);
+        log.mark_program(0x0000, 0xC000, DATA);
+        log.mark_program(0x3FFF, 0xFFFF, INDIRECT_DATA | DATA);
+        log.mark_program(0x4000, 0x8000, CODE);
+        log.mark_character(0x0010, RENDERED);
+        log.mark_character(0x0010, READ);
+        log.mark_character(0x2000, READ);
+        assert_eq!(log.program()[0x0000], CODE | DATA | 0x08);
+        assert_eq!(log.program()[0x3FFF], DATA | INDIRECT_DATA | 0x0C);
+        assert_eq!(log.character()[0x0010], RENDERED | READ);
+
+        // the window is where the byte was seen last
+        log.mark_program(0x0000, 0x8000, CODE);
+        assert_eq!(log.program()[0x0000], CODE | DATA);
+    }
+
+    // $C000: pointer $00 = $C000, LDA ($00),Y with Y = $60, LDA $C050,
+    //        PPUADDR = $0010, LDA $2007, JMP ($C070)
+    // $C040: JMP $C040
+    #[test]
+    fn test_logging() {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        let program = [
+            0xA9, 0x00, 0x85, 0x00, 0xA9, 0xC0, 0x85, 0x01, 0xA0, 0x60, 0xB1, 0x00, 0xAD, 0x50,
+            0xC0, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA9, 0x10, 0x8D, 0x06, 0x20, 0xAD, 0x07, 0x20,
+            0x6C, 0x70, 0xC0,
+        ];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        data[0x50..0x53].copy_from_slice(&[0x4C, 0x40, 0xC0]);
+        data[0x80..0x82].copy_from_slice(&[0x40, 0xC0]);
+        data[0x10 + 0x3FFC..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes = NES::new(&data);
+        assert!(nes.code_data_log().is_none());
+        nes.set_code_data_logging(true);
+        nes.frame();
+
+        let log = nes.code_data_log().unwrap();
+        let program = log.program();
+        // $C000-$FFFF is the upper window
+        assert_eq!(program[0x0000], CODE | 0x08);
+        assert_eq!(program[0x001E], CODE | 0x08);
+        assert_eq!(program[0x001F], 0x00);
+        assert_eq!(program[0x0050], DATA | 0x08);
+        assert_eq!(program[0x0060], DATA | INDIRECT_DATA | 0x08);
+        assert_eq!(program[0x0070], DATA | 0x08);
+        assert_eq!(program[0x0040], CODE | INDIRECT_CODE | 0x08);
+        assert_eq!(program[0x0041], CODE | 0x08);
+        // the reset vector was read before logging started
+        assert_eq!(program[0x3FFC], 0x00);
+        assert_eq!(log.character()[0x0010], READ);
+        assert_eq!(log.character()[0x0000], RENDERED);
+        drop(log);
+
+        let data = nes.code_data_log().unwrap().to_bytes();
+        nes.set_code_data_logging(false);
+        assert!(nes.code_data_log().is_none());
+        nes.load_code_data_log(&data).unwrap();
+        assert_eq!(nes.code_data_log().unwrap().to_bytes(), data);
+        assert!(nes.load_code_data_log(&data[1..]).is_err());
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut log = CodeDataLog::new(0x4000, 0x2000);
+        log.mark_program(0x0001, 0xC001, CODE);
+        log.mark_character(0x0002, RENDERED);
+        let data = log.to_bytes();
+        assert_eq!(data.len(), 0x6000);
+        assert_eq!(data[0x0001], CODE | 0x08);
+        assert_eq!(data[0x4002], RENDERED);
+
+        let log = CodeDataLog::from_bytes(&data, 0x4000, 0x2000).unwrap();
+        assert_eq!(log.to_bytes(), data);
+        assert_eq!(
+            CodeDataLog::from_bytes(&data, 0x8000, 0x2000).err(),
+            Some("CDL size 24576 does not match PRG 32768 + CHR 8192".to_string())
+        );
+        // CHR RAM carts only log PRG
+        assert_eq!(
+            CodeDataLog::from_bytes(&data[..0x4000], 0x4000, 0)
+                .unwrap()
+                .character()
+                .len(),
+            0
+        );
+    }
+}
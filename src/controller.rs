@@ -1,3 +1,5 @@
+use crate::state::{check_below, StateReader, StateWriter};
+
 #[derive(Debug)]
 pub struct Controller {
     key_state: [bool; 8],
@@ -19,8 +21,9 @@ impl Default for Controller {
 
 impl Controller {
     pub fn read(&mut self) -> bool {
-        let result = self.register[self.index];
-        self.index = self.index + 1;
+        // a standard controller returns 1 once all eight bits are read
+        let result = self.register.get(self.index).copied().unwrap_or(true);
+        self.index = (self.index + 1).min(8);
         result
     }
     pub fn write(&mut self, data: u8) {
@@ -48,6 +51,32 @@ impl Controller {
     pub fn key_up(&mut self, key: u8) {
         self.key_state[key as usize] = false;
     }
+    // Bit n for key n.
+    pub fn keys(&self) -> u8 {
+        (0..8).fold(0, |keys, i| keys | (self.key_state[i] as u8) << i)
+    }
+    pub fn set_keys(&mut self, keys: u8) {
+        for i in 0..8 {
+            self.key_state[i] = keys & (1 << i) != 0;
+        }
+    }
+    // The shift register, not the keys held on the host.
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bool(self.is_set);
+        state.write_u8(self.index as u8);
+        for bit in self.register {
+            state.write_bool(bit);
+        }
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.is_set = state.read_bool()?;
+        // 8 once every bit has been read
+        self.index = check_below("controller index", state.read_u8()?, 9)? as usize;
+        for bit in self.register.iter_mut() {
+            *bit = state.read_bool()?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
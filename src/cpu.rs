@@ -1,74 +1,278 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{interrupt::Interrupt, log, ppu::PPU, ram::RAM, Byte, Cycle, Word};
+use crate::{
+    interrupt::{Interrupt, InterruptKind},
+    log,
+    ram::RAM,
+    state::{StateReader, StateWriter},
+    Byte, Cycle, Word,
+};
 
 mod bus;
+pub mod call_stack;
 mod decoder;
+pub mod disassembler;
 mod executor;
-mod opcode;
+pub mod opcode;
 mod register;
+mod variant;
 
 pub use bus::CPUBus;
+pub use call_stack::{CallStack, FrameKind};
+pub use register::CPURegister;
+pub use variant::CpuVariant;
 
 const WRAM_SIZE: usize = 2048;
 pub type WRAM = RAM<WRAM_SIZE>;
+const PROGRAM_RAM_SIZE: usize = 0x2000;
+pub type ProgramRAM = RAM<PROGRAM_RAM_SIZE>;
 
-pub struct CPU<P: PPU> {
-    bus: CPUBus<P>,
+// What the CPU reads a byte for, so a bus can tell code from data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadKind {
+    Code,
+    // the first opcode after an indirect jump
+    IndirectCode,
+    Data,
+    // data reached through a zero page pointer
+    IndirectData,
+    // reads whose value the CPU throws away
+    Dummy,
+}
+
+// Everything the CPU sees of the outside world. The CPU calls `tick` once
+// per cycle, right before the access made in that cycle.
+pub trait Bus {
+    fn read(&mut self, address: Word) -> Byte;
+    fn read_as(&mut self, address: Word, _kind: ReadKind) -> Byte {
+        self.read(address)
+    }
+    // Reads without the side effects a read can have, for tooling.
+    fn peek(&self, address: Word) -> Byte;
+    fn write(&mut self, address: Word, data: Byte) -> ();
+    fn tick(&mut self) -> ();
+    // Cycles the CPU must sit out before its next instruction, e.g. OAM DMA.
+    fn stall(&mut self) -> Cycle {
+        0
+    }
+    // Told right before the CPU starts servicing an interrupt.
+    fn interrupt(&mut self, _kind: InterruptKind) -> () {}
+}
+
+pub struct CPU<B: Bus> {
+    bus: B,
     register: register::CPURegister,
     interrupt: Rc<RefCell<Interrupt>>,
+    is_jammed: bool,
+    cycle: u64,
+    variant: CpuVariant,
+    // when `service_interrupts` ran ahead of `run`, the cycle it started at
+    serviced_at: Option<u64>,
+    // set by the addressing mode for the bus to see what a read is for
+    is_indirect: bool,
+    is_indirect_jump: bool,
+    call_stack: Option<CallStack>,
 }
 
-impl<P: PPU> CPU<P> {
-    pub fn new(bus: CPUBus<P>, interrupt: Rc<RefCell<Interrupt>>) -> Self {
+impl<B: Bus> CPU<B> {
+    pub fn new(bus: B, interrupt: Rc<RefCell<Interrupt>>) -> Self {
         CPU {
             bus,
             register: register::CPURegister::default(),
             interrupt,
+            is_jammed: false,
+            cycle: 0,
+            variant: CpuVariant::default(),
+            serviced_at: None,
+            is_indirect: false,
+            is_indirect_jump: false,
+            call_stack: None,
         }
     }
 
     pub fn reset(&mut self) -> () {
         log("CPU reset...");
+        self.is_jammed = false;
+        self.serviced_at = None;
+        self.is_indirect_jump = false;
+        if let Some(call_stack) = self.call_stack.as_mut() {
+            call_stack.clear(self.cycle);
+        }
+        // the interrupt sequence with the stack writes turned into reads
+        let pc = self.register.get_pc();
+        self.dummy_read(pc);
+        self.dummy_read(pc);
+        for _ in 0..3 {
+            self.read_stack();
+            self.register.decrement_s();
+        }
+        self.register.set_i();
         let pc = self.read_word(0xFFFC);
         self.register.set_pc(if pc == 0 { 0x8000 } else { pc });
         log(&format!("PC: {:04X}", self.register.get_pc()));
     }
+    // Runs one instruction, ticking the bus once per memory access, and
+    // returns the number of cycles spent (DMA and interrupts included).
     pub fn run(&mut self) -> Cycle {
-        if self.interrupt.borrow().is_nmi() {
-            self.process_nmi();
-        }
-        if self.interrupt.borrow().is_irq() {
-            self.process_irq();
+        // a jammed CPU only comes back with a reset, but time keeps flowing
+        if self.is_jammed {
+            self.tick();
+            return 1;
         }
+        self.service_interrupts();
+        let start = self.serviced_at.take().unwrap_or(self.cycle);
 
+        let instruction_start = self.cycle;
         let opcode_byte = self.fetch_byte();
+        self.is_indirect = false;
+        self.is_indirect_jump = false;
         let opcode = opcode::get_opcode(opcode_byte);
         let decode_result = decoder::decode(self, &opcode);
 
         executor::execute(self, &opcode, decode_result.operand);
-        opcode.cycle + if decode_result.page_crossed { 1 } else { 0 }
+        // page crossings and taken branches only ever add to the base count
+        debug_assert!(self.cycle - instruction_start >= opcode.cycle as u64);
+        (self.cycle - start) as Cycle
+    }
+
+    // Runs the DMA stalls and the interrupt sequence `run` starts with, so
+    // tools can see the instruction that really runs next. Returns the
+    // interrupt taken; calling it again before `run` does nothing.
+    pub fn service_interrupts(&mut self) -> Option<InterruptKind> {
+        if self.is_jammed || self.serviced_at.is_some() {
+            return None;
+        }
+        self.serviced_at = Some(self.cycle);
+        for _ in 0..self.bus.stall() {
+            self.tick();
+        }
+        let mut kind = None;
+        if self.interrupt.borrow().is_nmi() {
+            self.bus.interrupt(InterruptKind::NMI);
+            self.process_nmi();
+            kind = Some(InterruptKind::NMI);
+        }
+        if self.interrupt.borrow().is_irq() && !self.register.get_i() {
+            self.bus.interrupt(InterruptKind::IRQ);
+            self.process_irq();
+            kind = Some(InterruptKind::IRQ);
+        }
+        if kind.is_some() {
+            self.is_indirect_jump = false;
+        }
+        kind
+    }
+
+    // The registers and interrupt lines; the bus is saved by its owner.
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        self.register.save_state(state);
+        self.interrupt.borrow().save_state(state);
+        state.write_bool(self.is_jammed);
+        state.write_u64(self.cycle);
+        state.write_bool(self.serviced_at.is_some());
+        state.write_u64(self.serviced_at.unwrap_or(0));
+        state.write_bool(self.is_indirect_jump);
+    }
+    // Profiling starts over, the saved calls are not known.
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.register.load_state(state)?;
+        self.interrupt.borrow_mut().load_state(state)?;
+        self.is_jammed = state.read_bool()?;
+        self.cycle = state.read_u64()?;
+        let is_serviced = state.read_bool()?;
+        let serviced_at = state.read_u64()?;
+        self.serviced_at = is_serviced.then_some(serviced_at);
+        self.is_indirect_jump = state.read_bool()?;
+        if self.call_stack.is_some() {
+            self.call_stack = Some(CallStack::new(self.cycle));
+        }
+        Ok(())
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+    // Decodes the instruction at `address` with the current registers.
+    pub fn disassemble(&self, address: Word) -> disassembler::Instruction {
+        disassembler::disassemble(&self.bus, address, Some(&self.register), self.variant)
+    }
+    // Follows JSR/RTS and interrupts from here on, profiling the routines.
+    pub fn set_call_tracking(&mut self, is_tracking: bool) -> () {
+        self.call_stack = is_tracking.then(|| CallStack::new(self.cycle));
+    }
+    pub fn call_stack(&self) -> Option<&CallStack> {
+        self.call_stack.as_ref()
+    }
+    pub fn call_stack_mut(&mut self) -> Option<&mut CallStack> {
+        self.call_stack.as_mut()
+    }
+    // Stops tracking until the call stack is put back with `set_call_stack`.
+    pub fn take_call_stack(&mut self) -> Option<CallStack> {
+        self.call_stack.take()
+    }
+    pub fn set_call_stack(&mut self, call_stack: Option<CallStack>) -> () {
+        self.call_stack = call_stack;
+    }
+    pub fn variant(&self) -> CpuVariant {
+        self.variant
+    }
+    pub fn set_variant(&mut self, variant: CpuVariant) -> () {
+        self.variant = variant;
     }
 
     fn fetch_byte(&mut self) -> Byte {
-        let data = self.read_byte(self.register.get_pc());
+        let kind = if self.is_indirect_jump {
+            ReadKind::IndirectCode
+        } else {
+            ReadKind::Code
+        };
+        self.tick();
+        let data = self.bus.read_as(self.register.get_pc(), kind);
         self.register.increment_pc_byte();
         data
     }
     fn fetch_word(&mut self) -> Word {
-        let data = self.read_word(self.register.get_pc());
-        self.register.increment_pc_word();
-        data
+        let lo = self.fetch_byte() as Word;
+        let hi = self.fetch_byte() as Word;
+        (hi << 8) | lo
+    }
+    // Every access takes one CPU cycle; the rest of the system catches up
+    // before the access lands.
+    fn tick(&mut self) -> () {
+        self.cycle += 1;
+        self.bus.tick();
     }
-    fn read_byte(&self, address: Word) -> Byte {
-        self.bus.read(address)
+    fn read_byte(&mut self, address: Word) -> Byte {
+        let kind = if self.is_indirect {
+            ReadKind::IndirectData
+        } else {
+            ReadKind::Data
+        };
+        self.tick();
+        self.bus.read_as(address, kind)
     }
-    fn read_word(&self, address: Word) -> Word {
-        self.bus.read(address) as Word | ((self.bus.read(address + 1) as Word) << 8)
+    fn dummy_read(&mut self, address: Word) -> () {
+        self.tick();
+        self.bus.read_as(address, ReadKind::Dummy);
+    }
+    fn read_word(&mut self, address: Word) -> Word {
+        let lo = self.read_byte(address) as Word;
+        let hi = self.read_byte(address.wrapping_add(1)) as Word;
+        (hi << 8) | lo
     }
     fn write(&mut self, address: Word, data: Byte) -> () {
+        self.tick();
         self.bus.write(address, data);
     }
+    fn read_stack(&mut self) -> () {
+        self.dummy_read(self.register.stack_address());
+    }
 
     fn push(&mut self, data: Byte) -> () {
         self.write(self.register.stack_address(), data);
@@ -96,35 +300,187 @@ impl<P: PPU> CPU<P> {
         self.register.set_pc((hi << 8) | lo);
     }
     fn branch(&mut self, address: Word) {
+        let pc = self.register.get_pc();
+        self.dummy_read(pc);
+        if pc & 0xFF00 != address & 0xFF00 {
+            self.dummy_read((pc & 0xFF00) | (address & 0x00FF));
+        }
         self.register.set_pc(address);
     }
+    fn enter_call(&mut self, kind: FrameKind, return_address: Word) -> () {
+        if let Some(call_stack) = self.call_stack.as_mut() {
+            let routine = self.register.get_pc();
+            call_stack.enter(
+                kind,
+                routine,
+                return_address,
+                self.register.get_s(),
+                self.cycle,
+            );
+        }
+    }
+    // `s` is the stack pointer from before the return pulled anything.
+    fn leave_call(&mut self, s: Byte) -> () {
+        if let Some(call_stack) = self.call_stack.as_mut() {
+            call_stack.leave(s, self.cycle);
+        }
+    }
+    // interrupts spend two cycles reading the next opcode without using it
+    fn read_interrupted_pc(&mut self) -> () {
+        let pc = self.register.get_pc();
+        self.dummy_read(pc);
+        self.dummy_read(pc);
+    }
     fn process_irq(&mut self) -> () {
         if self.register.get_i() {
             return;
         }
         self.interrupt.borrow_mut().clear_irq();
+        self.read_interrupted_pc();
         self.register.clear_b();
+        let return_address = self.register.get_pc();
         self.push_pc();
         self.push_status();
-        self.register.set_i();
+        self.set_interrupt_disable();
         self.set_pc_by_irq();
+        self.enter_call(FrameKind::IRQ, return_address);
     }
     fn set_pc_by_irq(&mut self) -> () {
-        self.register.set_pc(self.read_word(0xFFFE));
+        let pc = self.read_word(0xFFFE);
+        self.register.set_pc(pc);
     }
     fn process_nmi(&mut self) -> () {
         self.interrupt.borrow_mut().clear_nmi();
+        self.read_interrupted_pc();
         self.register.clear_b();
+        let return_address = self.register.get_pc();
         self.push_pc();
         self.push_status();
-        self.register.set_i();
+        self.set_interrupt_disable();
         self.set_pc_by_nmi();
+        self.enter_call(FrameKind::NMI, return_address);
+    }
+    fn set_interrupt_disable(&mut self) -> () {
+        self.register.set_i();
+        if self.variant.clears_decimal_on_interrupt() {
+            self.register.clear_d();
+        }
     }
     fn set_pc_by_nmi(&mut self) -> () {
-        self.register.set_pc(self.read_word(0xFFFA));
+        let pc = self.read_word(0xFFFA);
+        self.register.set_pc(pc);
+    }
+
+    pub fn is_jammed(&self) -> bool {
+        self.is_jammed
+    }
+    fn jam(&mut self) -> () {
+        log(&format!(
+            "CPU jammed at {:04X}",
+            self.register.get_pc().wrapping_sub(1)
+        ));
+        self.is_jammed = true;
     }
 
-    fn get_register(&mut self) -> &mut register::CPURegister {
+    pub fn register(&self) -> &CPURegister {
+        &self.register
+    }
+    pub fn get_register(&mut self) -> &mut CPURegister {
         &mut self.register
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 64K of plain RAM, enough to run the core without the rest of the NES
+    struct FlatBus {
+        memory: Vec<Byte>,
+    }
+    impl Bus for FlatBus {
+        fn read(&mut self, address: Word) -> Byte {
+            self.memory[address as usize]
+        }
+        fn peek(&self, address: Word) -> Byte {
+            self.memory[address as usize]
+        }
+        fn write(&mut self, address: Word, data: Byte) -> () {
+            self.memory[address as usize] = data;
+        }
+        fn tick(&mut self) -> () {}
+    }
+
+    fn prepare_cpu(program: &[Byte]) -> CPU<FlatBus> {
+        let mut memory = vec![0x00; 0x10000];
+        memory[0x8000..0x8000 + program.len()].copy_from_slice(program);
+        // reset vector to $8000, NMI and IRQ vectors to $9000
+        memory[0xFFFA..].copy_from_slice(&[0x00, 0x90, 0x00, 0x80, 0x00, 0x90]);
+        let mut cpu = CPU::new(
+            FlatBus { memory },
+            Rc::new(RefCell::new(Interrupt::default())),
+        );
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn test_flat_bus() {
+        // LDX #$05; loop: DEX; TXA; STA $0200,X; BNE loop
+        let mut cpu = prepare_cpu(&[0xA2, 0x05, 0xCA, 0x8A, 0x9D, 0x00, 0x02, 0xD0, 0xF9]);
+        assert_eq!(cpu.cycle(), 7);
+        assert_eq!(cpu.register.get_s(), 0xFD);
+        for _ in 0..21 {
+            cpu.run();
+        }
+        assert_eq!(cpu.register.get_x(), 0x00);
+        assert_eq!(cpu.register.get_pc(), 0x8009);
+        assert_eq!(cpu.bus().memory[0x0200..0x0205], [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_opcode_cycles() {
+        // every opcode runs with operands pointing at $0010, so nothing
+        // crosses a page and the table count must be exact
+        for byte in 0x00..=0xFF {
+            let opcode = opcode::get_opcode(byte);
+            let mut cpu = prepare_cpu(&[byte, 0x10, 0x00]);
+            // BPL, BVC, BCC and BNE are taken with the flags cleared
+            let is_taken = matches!(byte, 0x10 | 0x50 | 0x90 | 0xD0);
+            let expected = opcode.cycle + if is_taken { 1 } else { 0 };
+            assert_eq!(cpu.run(), expected, "opcode {:02X}", byte);
+        }
+    }
+
+    #[test]
+    fn test_page_crossing_cycles() {
+        // LDA $80F0,X with X = $20 pays for the carry into the high byte
+        let mut cpu = prepare_cpu(&[0xA2, 0x20, 0xBD, 0xF0, 0x80, 0xBD, 0x00, 0x80]);
+        assert_eq!(cpu.run(), 2);
+        assert_eq!(cpu.run(), 5);
+        assert_eq!(cpu.run(), 4);
+
+        // STA and INC always spend the fixup cycle
+        let mut cpu = prepare_cpu(&[0x9D, 0x00, 0x02, 0xFE, 0x00, 0x02]);
+        assert_eq!(cpu.run(), 5);
+        assert_eq!(cpu.run(), 7);
+
+        // a taken branch into the next page costs two extra cycles
+        let mut cpu = prepare_cpu(&[]);
+        cpu.bus_mut().memory[0x80F0..0x80F2].copy_from_slice(&[0x10, 0x20]);
+        cpu.register.set_pc(0x80F0);
+        assert_eq!(cpu.run(), 4);
+        assert_eq!(cpu.register.get_pc(), 0x8112);
+    }
+
+    #[test]
+    fn test_interrupt_cycles() {
+        let mut cpu = prepare_cpu(&[]);
+        cpu.bus_mut().memory[0x9000] = 0xEA;
+        cpu.interrupt.borrow_mut().set_nmi();
+        // seven cycles for the NMI sequence and two for the NOP at $9000
+        assert_eq!(cpu.run(), 9);
+        assert_eq!(cpu.register.get_pc(), 0x9001);
+        assert_eq!(cpu.read_word(0x01FC), 0x8000);
+    }
+}
@@ -1,15 +1,39 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{controller::Controller, dma::DMA, log, ppu::PPU, rom::ROM, Byte, Word};
+use crate::{
+    cdl::{self, CodeDataLog},
+    controller::Controller,
+    debugger::{AccessKind, MemoryAccess},
+    dma::{DMACycle, DMA},
+    events::{EventKind, EventLog},
+    interrupt::InterruptKind,
+    log,
+    ppu::{RenderingData, PPU},
+    region::Region,
+    rom::ROM,
+    state::{check_below, StateReader, StateWriter},
+    Byte, Cycle, Word,
+};
 
-use super::WRAM;
+use super::{Bus, ProgramRAM, ReadKind, WRAM};
 
 pub struct CPUBus<P: PPU> {
     program_rom: ROM,
     wram: Rc<RefCell<WRAM>>,
+    // the cartridge's work or battery RAM at $6000-$7FFF
+    program_ram: ProgramRAM,
     ppu: Rc<RefCell<P>>,
     controller: Rc<RefCell<Controller>>,
-    dma: Rc<RefCell<DMA<P>>>,
+    dma: Rc<RefCell<DMA>>,
+    region: Region,
+    cycle: u64,
+    // leftover PPU dots when the clock ratio is not an integer (PAL)
+    dot_remainder: Cycle,
+    rendering_data: Option<RenderingData>,
+    // every access since the last take, while the debugger watches memory
+    accesses: Option<Vec<MemoryAccess>>,
+    code_data_log: Option<Rc<RefCell<CodeDataLog>>>,
+    event_log: Option<Rc<RefCell<EventLog>>>,
 }
 
 impl<P: PPU> CPUBus<P> {
@@ -18,17 +42,205 @@ impl<P: PPU> CPUBus<P> {
         wram: Rc<RefCell<WRAM>>,
         ppu: Rc<RefCell<P>>,
         controller: Rc<RefCell<Controller>>,
-        dma: Rc<RefCell<DMA<P>>>,
+        dma: Rc<RefCell<DMA>>,
     ) -> Self {
         CPUBus {
             program_rom,
             wram,
+            program_ram: ProgramRAM::default(),
             ppu,
             controller,
             dma,
+            region: Region::default(),
+            cycle: 0,
+            dot_remainder: 0,
+            rendering_data: None,
+            accesses: None,
+            code_data_log: None,
+            event_log: None,
         }
     }
-    pub fn read(&self, address: Word) -> Byte {
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.dot_remainder = 0;
+    }
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+    pub fn program_rom_size(&self) -> usize {
+        self.program_rom.size()
+    }
+    pub fn program_rom(&self) -> &ROM {
+        &self.program_rom
+    }
+    pub fn program_rom_mut(&mut self) -> &mut ROM {
+        &mut self.program_rom
+    }
+    pub fn program_ram(&self) -> &ProgramRAM {
+        &self.program_ram
+    }
+    pub fn program_ram_mut(&mut self) -> &mut ProgramRAM {
+        &mut self.program_ram
+    }
+    // Changes memory like `peek` sees it. Registers are left alone, and ROM
+    // is patched in place.
+    pub fn poke(&mut self, address: Word, data: Byte) -> () {
+        match address {
+            0x0000..=0x1FFF => self.wram.borrow_mut().write(address % 0x0800, data),
+            0x2000..=0x5FFF => {}
+            0x6000..=0x7FFF => self.program_ram.write(address - 0x6000, data),
+            0x8000..=0xFFFF => {
+                let offset = self.program_rom_offset(address);
+                self.program_rom.write(offset, data);
+            }
+        }
+    }
+    pub fn take_rendering_data(&mut self) -> Option<RenderingData> {
+        self.rendering_data.take()
+    }
+    // RAM and everything hanging off the bus except the PPU. ROM is not saved.
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        self.wram.borrow().save_state(state);
+        self.program_ram.save_state(state);
+        state.write_u64(self.cycle);
+        state.write_u32(self.dot_remainder);
+        self.controller.borrow().save_state(state);
+        self.dma.borrow().save_state(state);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.wram.borrow_mut().load_state(state)?;
+        self.program_ram.load_state(state)?;
+        self.cycle = state.read_u64()?;
+        // the region is loaded first
+        self.dot_remainder = check_below(
+            "dot remainder",
+            state.read_u32()?,
+            self.region.clock_ratio().1,
+        )?;
+        self.controller.borrow_mut().load_state(state)?;
+        self.dma.borrow_mut().load_state(state)?;
+        self.rendering_data = None;
+        Ok(())
+    }
+    pub fn set_access_logging(&mut self, is_logging: bool) -> () {
+        self.accesses = if is_logging { Some(Vec::new()) } else { None };
+    }
+    pub fn set_code_data_log(&mut self, code_data_log: Option<Rc<RefCell<CodeDataLog>>>) -> () {
+        self.code_data_log = code_data_log;
+    }
+    pub fn set_event_log(&mut self, event_log: Option<Rc<RefCell<EventLog>>>) -> () {
+        self.event_log = event_log;
+    }
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        self.accesses
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+    fn log_access(&mut self, address: Word, data: Byte, kind: AccessKind) -> () {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(MemoryAccess {
+                address,
+                data,
+                kind,
+            });
+        }
+    }
+    fn record_event(&self, kind: EventKind) -> () {
+        if let Some(event_log) = &self.event_log {
+            let (scanline, dot) = self.ppu.borrow().position();
+            event_log.borrow_mut().record(kind, scanline, dot);
+        }
+    }
+    fn read_program_rom(&self, address: Word) -> Byte {
+        self.program_rom.read(self.program_rom_offset(address))
+    }
+    fn program_rom_offset(&self, address: Word) -> Word {
+        match address {
+            0xC000..=0xFFFF if self.program_rom.size() <= 0x4000 => address - 0xC000,
+            _ => address - 0x8000,
+        }
+    }
+}
+
+impl<P: PPU> Bus for CPUBus<P> {
+    // Advances everything clocked alongside the CPU by one CPU cycle.
+    fn tick(&mut self) -> () {
+        self.cycle += 1;
+        let (numerator, denominator) = self.region.clock_ratio();
+        let dots = numerator + self.dot_remainder;
+        self.dot_remainder = dots % denominator;
+        if let Some(rendering_data) = self.ppu.borrow_mut().run(dots / denominator) {
+            self.rendering_data = Some(rendering_data);
+        }
+        // a running OAM DMA makes its access in each cycle the CPU is halted
+        let dma_cycle = self.dma.borrow_mut().step();
+        match dma_cycle {
+            Some(DMACycle::Get(address)) => {
+                let data = self.read(address);
+                self.dma.borrow_mut().latch(data);
+            }
+            Some(DMACycle::Put(index, data)) => self.ppu.borrow_mut().transfer_sprite(index, data),
+            Some(DMACycle::Halt) | None => {}
+        }
+    }
+    // A pending OAM DMA halts the CPU for its duration.
+    fn stall(&mut self) -> Cycle {
+        self.dma.borrow_mut().start(self.cycle % 2 == 1)
+    }
+    fn read(&mut self, address: Word) -> Byte {
+        let data = self.read_mapped(address);
+        self.log_access(address, data, AccessKind::Read);
+        data
+    }
+    fn read_as(&mut self, address: Word, kind: ReadKind) -> Byte {
+        let data = self.read(address);
+        if let (Some(code_data_log), 0x8000..=0xFFFF) = (&self.code_data_log, address) {
+            let flags = match kind {
+                ReadKind::Code => cdl::CODE,
+                ReadKind::IndirectCode => cdl::CODE | cdl::INDIRECT_CODE,
+                ReadKind::Data => cdl::DATA,
+                ReadKind::IndirectData => cdl::DATA | cdl::INDIRECT_DATA,
+                ReadKind::Dummy => return data,
+            };
+            let offset = self.program_rom_offset(address) as usize;
+            code_data_log
+                .borrow_mut()
+                .mark_program(offset, address, flags);
+        }
+        data
+    }
+    fn peek(&self, address: Word) -> Byte {
+        match address {
+            0x0000..=0x1FFF => self.wram.borrow().read(address % 0x0800),
+            // reading a register can change it, show an undriven bus instead
+            0x2000..=0x401F => 0xFF,
+            0x4020..=0x5FFF => 0x00,
+            0x6000..=0x7FFF => self.program_ram.read(address - 0x6000),
+            0x8000..=0xFFFF => self.read_program_rom(address),
+        }
+    }
+    fn write(&mut self, address: Word, data: Byte) -> () {
+        self.log_access(address, data, AccessKind::Write);
+        match address {
+            0x2000..=0x3FFF => {
+                self.record_event(EventKind::Write((address - 0x2000) % 0x0008 + 0x2000, data))
+            }
+            0x4014 | 0x8000..=0xFFFF => self.record_event(EventKind::Write(address, data)),
+            _ => {}
+        }
+        self.write_mapped(address, data);
+    }
+    fn interrupt(&mut self, kind: InterruptKind) -> () {
+        self.record_event(match kind {
+            InterruptKind::NMI => EventKind::NMI,
+            InterruptKind::IRQ => EventKind::IRQ,
+        });
+    }
+}
+
+impl<P: PPU> CPUBus<P> {
+    fn read_mapped(&mut self, address: Word) -> Byte {
         match address {
             0x0000..=0x1FFF => self.wram.borrow().read(address % 0x0800),
             0x2000..=0x3FFF => self
@@ -60,18 +272,11 @@ impl<P: PPU> CPUBus<P> {
                 ));
                 0x00
             }
-            0x6000..=0x7FFF => {
-                log(&format!("SRAM is not implemented yet: {:04X}", address));
-                0x00
-            }
-            0x8000..=0xBFFF => self.program_rom.read(address - 0x8000),
-            0xC000..=0xFFFF if self.program_rom.size() <= 0x4000 => {
-                self.program_rom.read(address - 0xC000)
-            }
-            0xC000..=0xFFFF => self.program_rom.read(address - 0x8000),
+            0x6000..=0x7FFF => self.program_ram.read(address - 0x6000),
+            0x8000..=0xFFFF => self.read_program_rom(address),
         }
     }
-    pub fn write(&mut self, address: Word, data: Byte) -> () {
+    fn write_mapped(&mut self, address: Word, data: Byte) -> () {
         match address {
             0x0000..=0x1FFF => self.wram.borrow_mut().write(address % 0x0800, data),
             0x2000..=0x3FFF => self
@@ -95,9 +300,7 @@ impl<P: PPU> CPUBus<P> {
                     address
                 ));
             }
-            0x6000..=0x7FFF => {
-                log(&format!("SRAM is not implemented yet: {:04X}", address));
-            }
+            0x6000..=0x7FFF => self.program_ram.write(address - 0x6000, data),
             0x8000..=0xFFFF => {
                 log(&format!(
                     "Write to ROM is not implemented yet: {:04X}",
@@ -131,7 +334,7 @@ mod test {
         let wram = Rc::new(RefCell::new(WRAM::default()));
         let ppu = Rc::new(RefCell::new(MockPPU::new()));
         let controller = Rc::new(RefCell::new(Controller::default()));
-        let dma = Rc::new(RefCell::new(DMA::new(wram.clone(), ppu.clone())));
+        let dma = Rc::new(RefCell::new(DMA::default()));
 
         let mut bus = CPUBus::new(
             program_rom,
@@ -224,13 +427,23 @@ mod test {
                 .with(predicate::eq(i), predicate::eq(i))
                 .return_const(());
         }
-        dma.borrow_mut().run();
+        ppu.borrow_mut().expect_run().returning(|_| None);
+        for _ in 0..bus.stall() {
+            bus.tick();
+        }
 
         // ROM
         assert_eq!(bus.read(0x8000), 0x00);
         assert_eq!(bus.read(0xBFFF), 0xFF);
         assert_eq!(bus.read(0xC000), 0x01);
         assert_eq!(bus.read(0xFFFF), 0x00);
+
+        // PRG RAM
+        bus.write(0x6000, 0x09);
+        bus.write(0x7FFF, 0x0A);
+        assert_eq!(bus.read(0x6000), 0x09);
+        assert_eq!(bus.read(0x7FFF), 0x0A);
+        assert_eq!(bus.program_ram().read(0x1FFF), 0x0A);
     }
 
     #[test]
@@ -243,9 +456,9 @@ mod test {
         let wram = Rc::new(RefCell::new(WRAM::default()));
         let ppu = Rc::new(RefCell::new(MockPPU::new()));
         let controller = Rc::new(RefCell::new(Controller::default()));
-        let dma = Rc::new(RefCell::new(DMA::new(wram.clone(), ppu.clone())));
+        let dma = Rc::new(RefCell::new(DMA::default()));
 
-        let bus = CPUBus::new(
+        let mut bus = CPUBus::new(
             program_rom,
             wram.clone(),
             ppu.clone(),
@@ -258,4 +471,79 @@ mod test {
         assert_eq!(bus.read(0xC000), 0x00);
         assert_eq!(bus.read(0xFFFF), 0xFF);
     }
+
+    #[test]
+    fn test_dma_through_bus() {
+        let program_rom = ROM::new(vec![0; 0x4000]);
+        let wram = Rc::new(RefCell::new(WRAM::default()));
+        let ppu = Rc::new(RefCell::new(MockPPU::new()));
+        let controller = Rc::new(RefCell::new(Controller::default()));
+        let dma = Rc::new(RefCell::new(DMA::default()));
+        let mut bus = CPUBus::new(program_rom, wram, ppu.clone(), controller, dma);
+        bus.set_access_logging(true);
+
+        // from PRG RAM, past the end of WRAM
+        for i in 0..=0xff {
+            bus.write(0x6000 + i, (0xff - i) as u8);
+        }
+        bus.write(0x4014, 0x60);
+        bus.take_accesses();
+        for i in 0..=0xff {
+            ppu.borrow_mut()
+                .expect_transfer_sprite()
+                .with(predicate::eq(i), predicate::eq(0xff - i))
+                .times(1)
+                .return_const(());
+        }
+        ppu.borrow_mut().expect_run().returning(|_| None);
+        // halted on an even cycle, so no alignment cycle
+        assert_eq!(bus.stall(), 513);
+        bus.tick();
+        assert!(bus.take_accesses().is_empty());
+        // one read per get/put pair
+        for i in 0..=0xff {
+            bus.tick();
+            let accesses = bus.take_accesses();
+            assert_eq!(accesses.len(), 1);
+            assert_eq!(accesses[0].address, 0x6000 + i);
+            assert_eq!(accesses[0].kind, AccessKind::Read);
+            bus.tick();
+            assert!(bus.take_accesses().is_empty());
+        }
+        ppu.borrow_mut().checkpoint();
+
+        // the transfer left the bus on an odd cycle
+        bus.write(0x4014, 0x60);
+        assert_eq!(bus.stall(), 514);
+    }
+
+    #[test]
+    fn test_peek_poke() {
+        let program_rom = ROM::new(vec![0; 0x4000]);
+        let wram = Rc::new(RefCell::new(WRAM::default()));
+        // the mock has no expectations, so touching a register would panic
+        let ppu = Rc::new(RefCell::new(MockPPU::new()));
+        let controller = Rc::new(RefCell::new(Controller::default()));
+        let dma = Rc::new(RefCell::new(DMA::default()));
+        let mut bus = CPUBus::new(program_rom, wram.clone(), ppu, controller.clone(), dma);
+
+        bus.poke(0x0801, 0x01);
+        bus.poke(0x2002, 0x02);
+        bus.poke(0x4016, 0x03);
+        bus.poke(0x6001, 0x04);
+        bus.poke(0xC002, 0x05);
+        assert_eq!(wram.borrow().read(0x0001), 0x01);
+        assert_eq!(bus.peek(0x0001), 0x01);
+        assert_eq!(bus.peek(0x2002), 0xFF);
+        assert_eq!(bus.peek(0x6001), 0x04);
+        // 16K PRG is mirrored
+        assert_eq!(bus.peek(0x8002), 0x05);
+        assert_eq!(bus.program_rom().read(0x0002), 0x05);
+
+        controller.borrow_mut().key_down(0);
+        controller.borrow_mut().write(0x01);
+        controller.borrow_mut().write(0x00);
+        bus.peek(0x4016);
+        assert_eq!(bus.read(0x4016), 0x01);
+    }
 }
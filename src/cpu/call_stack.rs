@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use crate::{Byte, Word};
+
+// BRK goes through the IRQ vector and shows up as an IRQ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Subroutine,
+    NMI,
+    IRQ,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub routine: Word,
+    pub return_address: Word,
+    // S right after the return address was pushed
+    pub s: Byte,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoutineStats {
+    pub calls: u64,
+    // cycles spent in the routine and everything it called
+    pub inclusive: u64,
+    // cycles spent in the routine itself
+    pub exclusive: u64,
+}
+
+// Cycles per routine; `None` is code run outside any call, e.g. the main
+// loop after reset.
+pub type Profile = HashMap<Option<Word>, RoutineStats>;
+
+// The calls and interrupts the CPU is in, matched to their returns by the
+// stack pointer so pushed return addresses (the RTS trick) and dropped frames
+// do not confuse it. Also profiles the cycles spent in each routine.
+pub struct CallStack {
+    frames: Vec<Frame>,
+    last_cycle: u64,
+    frame_profile: Profile,
+    last_frame_profile: Profile,
+    session_profile: Profile,
+}
+
+impl CallStack {
+    pub fn new(cycle: u64) -> Self {
+        CallStack {
+            frames: Vec::new(),
+            last_cycle: cycle,
+            frame_profile: Profile::new(),
+            last_frame_profile: Profile::new(),
+            session_profile: Profile::new(),
+        }
+    }
+
+    // Innermost last.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+    // The video frame finished before the current one.
+    pub fn last_frame_profile(&self) -> &Profile {
+        &self.last_frame_profile
+    }
+    pub fn session_profile(&self) -> &Profile {
+        &self.session_profile
+    }
+
+    pub fn enter(
+        &mut self,
+        kind: FrameKind,
+        routine: Word,
+        return_address: Word,
+        s: Byte,
+        cycle: u64,
+    ) -> () {
+        self.account(cycle);
+        self.frames.push(Frame {
+            kind,
+            routine,
+            return_address,
+            s,
+        });
+        for profile in [&mut self.frame_profile, &mut self.session_profile] {
+            profile.entry(Some(routine)).or_default().calls += 1;
+        }
+    }
+    // `s` is the stack pointer before RTS or RTI pulled anything.
+    pub fn leave(&mut self, s: Byte, cycle: u64) -> () {
+        self.account(cycle);
+        // frames below S were dropped without returning
+        while self.frames.last().is_some_and(|frame| frame.s < s) {
+            self.frames.pop();
+        }
+        if self.frames.last().is_some_and(|frame| frame.s == s) {
+            self.frames.pop();
+        }
+    }
+    pub fn end_frame(&mut self, cycle: u64) -> () {
+        self.account(cycle);
+        self.last_frame_profile = std::mem::take(&mut self.frame_profile);
+    }
+    pub fn clear(&mut self, cycle: u64) -> () {
+        self.account(cycle);
+        self.frames.clear();
+    }
+
+    // Gives the cycles since the last event to the routines on the stack.
+    fn account(&mut self, cycle: u64) -> () {
+        let cycles = cycle - self.last_cycle;
+        self.last_cycle = cycle;
+        let current = self.frames.last().map(|frame| frame.routine);
+        let mut routines = vec![None];
+        for frame in &self.frames {
+            // recursion only counts once
+            if !routines.contains(&Some(frame.routine)) {
+                routines.push(Some(frame.routine));
+            }
+        }
+        for profile in [&mut self.frame_profile, &mut self.session_profile] {
+            for routine in &routines {
+                profile.entry(*routine).or_default().inclusive += cycles;
+            }
+            profile.entry(current).or_default().exclusive += cycles;
+        }
+    }
+}
+
+// A table of the routines by inclusive cycles, named by `name`.
+pub fn report(profile: &Profile, name: impl Fn(Word) -> String) -> String {
+    let mut routines = profile.iter().collect::<Vec<_>>();
+    routines.sort_by(|(a_routine, a), (b_routine, b)| {
+        b.inclusive
+            .cmp(&a.inclusive)
+            .then(b.exclusive.cmp(&a.exclusive))
+            .then(a_routine.cmp(b_routine))
+    });
+    let total = profile
+        .values()
+        .map(|stats| stats.exclusive)
+        .sum::<u64>()
+        .max(1);
+    let mut text = format!(
+        "{:<24} {:>8} {:>12} {:>6} {:>12} {:>6}\n",
+        "routine", "calls", "inclusive", "%", "exclusive", "%"
+    );
+    for (routine, stats) in routines {
+        let routine = routine.map_or("(root)".to_string(), &name);
+        text += &format!(
+            "{:<24} {:>8} {:>12} {:>6.1} {:>12} {:>6.1}\n",
+            routine,
+            stats.calls,
+            stats.inclusive,
+            stats.inclusive as f64 * 100.0 / total as f64,
+            stats.exclusive,
+            stats.exclusive as f64 * 100.0 / total as f64
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(profile: &Profile, routine: Option<Word>) -> (u64, u64, u64) {
+        let stats = profile[&routine];
+        (stats.calls, stats.inclusive, stats.exclusive)
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let mut call_stack = CallStack::new(0);
+        call_stack.enter(FrameKind::Subroutine, 0xC100, 0xC003, 0xFB, 10);
+        call_stack.enter(FrameKind::Subroutine, 0xC200, 0xC103, 0xF9, 30);
+        assert_eq!(call_stack.frames().len(), 2);
+        call_stack.leave(0xF9, 60);
+        call_stack.leave(0xFB, 70);
+        assert!(call_stack.frames().is_empty());
+        call_stack.end_frame(100);
+
+        let profile = call_stack.last_frame_profile();
+        assert_eq!(stats(profile, None), (0, 100, 40));
+        assert_eq!(stats(profile, Some(0xC100)), (1, 60, 30));
+        assert_eq!(stats(profile, Some(0xC200)), (1, 30, 30));
+        assert_eq!(call_stack.session_profile(), profile);
+
+        // the next frame starts from zero, the session keeps adding up
+        call_stack.enter(FrameKind::NMI, 0xC300, 0xC010, 0xFA, 110);
+        call_stack.end_frame(120);
+        assert_eq!(
+            stats(call_stack.last_frame_profile(), Some(0xC300)),
+            (1, 10, 10)
+        );
+        assert_eq!(stats(call_stack.session_profile(), None), (0, 120, 50));
+        assert_eq!(call_stack.frames()[0].kind, FrameKind::NMI);
+    }
+
+    #[test]
+    fn test_unbalanced_returns() {
+        let mut call_stack = CallStack::new(0);
+        call_stack.enter(FrameKind::Subroutine, 0xC100, 0xC003, 0xFB, 0);
+        // a pushed address returned to with RTS is a jump, not a return
+        call_stack.leave(0xF9, 10);
+        assert_eq!(call_stack.frames().len(), 1);
+        // the routine dropped its return address and its caller returns
+        call_stack.enter(FrameKind::Subroutine, 0xC200, 0xC103, 0xF9, 20);
+        call_stack.leave(0xFB, 30);
+        assert!(call_stack.frames().is_empty());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut call_stack = CallStack::new(0);
+        call_stack.enter(FrameKind::Subroutine, 0xC100, 0xC003, 0xFB, 25);
+        call_stack.leave(0xFB, 100);
+        call_stack.end_frame(100);
+        let text = report(call_stack.last_frame_profile(), |address| {
+            format!("${:04X}", address)
+        });
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "routine                     calls    inclusive      %    exclusive      %"
+        );
+        assert_eq!(
+            lines[1],
+            "(root)                          0          100  100.0           25   25.0"
+        );
+        assert_eq!(
+            lines[2],
+            "$C100                           1           75   75.0           75   75.0"
+        );
+    }
+}
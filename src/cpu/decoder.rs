@@ -1,8 +1,8 @@
-use crate::{ppu::PPU, Word};
+use crate::Word;
 
 use super::{
-    opcode::{Addressing, Opcode},
-    CPU,
+    opcode::{Addressing, Opcode, OpcodeBaseName},
+    Bus, CPU,
 };
 
 #[derive(Debug)]
@@ -11,13 +11,18 @@ pub struct DecodeResult {
     pub page_crossed: bool,
 }
 
-pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
+pub fn decode<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode) -> DecodeResult {
     match opcode.addressing {
-        Addressing::Implied | Addressing::Accumulator => DecodeResult {
-            // dummy value
-            operand: 0x0000,
-            page_crossed: false,
-        },
+        Addressing::Implied | Addressing::Accumulator => {
+            // the byte after the opcode is read and discarded
+            let pc = cpu.get_register().get_pc();
+            cpu.dummy_read(pc);
+            DecodeResult {
+                // dummy value
+                operand: 0x0000,
+                page_crossed: false,
+            }
+        }
         Addressing::Immediate => {
             let value = cpu.fetch_byte() as Word;
             DecodeResult {
@@ -34,6 +39,7 @@ pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
         }
         Addressing::ZeroPageX => {
             let base = cpu.fetch_byte() as Word;
+            cpu.dummy_read(base);
             let offset = cpu.get_register().get_x() as Word;
             let address = (base + offset) & 0x00FF;
             DecodeResult {
@@ -43,6 +49,7 @@ pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
         }
         Addressing::ZeroPageY => {
             let base = cpu.fetch_byte() as Word;
+            cpu.dummy_read(base);
             let offset = cpu.get_register().get_y() as Word;
             let address = (base + offset) & 0x00FF;
             DecodeResult {
@@ -59,6 +66,17 @@ pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
                 page_crossed: page_crossed(base, address),
             }
         }
+        Addressing::Absolute if opcode.base_name == OpcodeBaseName::JSR => {
+            // the return address is pushed before the high byte is fetched
+            let lo = cpu.fetch_byte() as Word;
+            cpu.read_stack();
+            cpu.push_pc();
+            let hi = cpu.fetch_byte() as Word;
+            DecodeResult {
+                operand: (hi << 8) | lo,
+                page_crossed: false,
+            }
+        }
         Addressing::Absolute => {
             let address = cpu.fetch_word();
             DecodeResult {
@@ -70,24 +88,33 @@ pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
             let base = cpu.fetch_word();
             let offset = cpu.get_register().get_x();
             let address = (base as u32 + offset as u32) as Word;
-            DecodeResult {
+            let result = DecodeResult {
                 operand: address,
                 page_crossed: page_crossed(base, address),
-            }
+            };
+            read_uncorrected_address(cpu, opcode, base, &result);
+            result
         }
         Addressing::AbsoluteY => {
             let base = cpu.fetch_word();
             let offset = cpu.get_register().get_y();
             let address = (base as u32 + offset as u32) as Word;
-            DecodeResult {
+            let result = DecodeResult {
                 operand: address,
                 page_crossed: page_crossed(base, address),
-            }
+            };
+            read_uncorrected_address(cpu, opcode, base, &result);
+            result
         }
         Addressing::Indirect => {
             let lo = cpu.fetch_word();
-            let hi = (lo & 0xff00) | (lo as u8).wrapping_add(1) as u16;
+            let hi = if cpu.variant().has_indirect_jump_bug() {
+                (lo & 0xff00) | (lo as u8).wrapping_add(1) as u16
+            } else {
+                lo.wrapping_add(1)
+            };
             let address = cpu.read_byte(lo) as u16 | (cpu.read_byte(hi) as u16) << 8;
+            cpu.is_indirect_jump = true;
             DecodeResult {
                 operand: address,
                 page_crossed: false,
@@ -95,12 +122,14 @@ pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
         }
         Addressing::IndirectX => {
             let base = cpu.fetch_byte() as Word;
+            cpu.dummy_read(base);
             let offset = cpu.get_register().get_x() as Word;
             let address = (base + offset) & 0x00FF;
 
             let indirect_address_low = cpu.read_byte(address) as Word;
             let indirect_address_high = cpu.read_byte((address + 1) & 0x00FF) as Word;
             let indirect_address = (indirect_address_high << 8) | indirect_address_low;
+            cpu.is_indirect = true;
 
             DecodeResult {
                 operand: indirect_address,
@@ -114,14 +143,31 @@ pub fn decode<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode) -> DecodeResult {
             let address = (address_high << 8) | address_low;
             let offset = cpu.get_register().get_y() as Word;
             let indirect_address = (address as u32 + offset as u32) as Word;
-            DecodeResult {
+            cpu.is_indirect = true;
+            let result = DecodeResult {
                 operand: indirect_address,
                 page_crossed: page_crossed(indirect_address, address),
-            }
+            };
+            read_uncorrected_address(cpu, opcode, address, &result);
+            result
         }
     }
 }
 
+// Indexing first reads from the address with only the low byte adjusted.
+// Reads use that value when no page is crossed, other instructions always
+// spend the cycle.
+fn read_uncorrected_address<B: Bus>(
+    cpu: &mut CPU<B>,
+    opcode: &Opcode,
+    base: Word,
+    result: &DecodeResult,
+) -> () {
+    if result.page_crossed || opcode.base_name.is_write() {
+        cpu.dummy_read((base & 0xFF00) | (result.operand & 0x00FF));
+    }
+}
+
 fn page_crossed(address1: Word, address2: Word) -> bool {
     address1 & 0xFF00 != address2 & 0xFF00
 }
@@ -140,16 +186,13 @@ mod tests {
 
     use super::*;
 
-    fn prepare_cpu(program_rom: ROM) -> CPU<PPUImpl> {
+    fn prepare_cpu(program_rom: ROM) -> CPU<CPUBus<PPUImpl>> {
         let interrupt = Rc::new(RefCell::new(interrupt::Interrupt::default()));
         let ppu_bus = PPUBus::new(ROM::new(vec![]), false);
         let ppu = Rc::new(RefCell::new(PPUImpl::new(ppu_bus, interrupt.clone())));
         let controller = Rc::new(RefCell::new(Controller::default()));
         let wram = Rc::new(RefCell::new(crate::cpu::WRAM::default()));
-        let dma = Rc::new(RefCell::new(crate::dma::DMA::new(
-            wram.clone(),
-            ppu.clone(),
-        )));
+        let dma = Rc::new(RefCell::new(crate::dma::DMA::default()));
         let cpu_bus = CPUBus::new(
             program_rom,
             wram.clone(),
@@ -162,7 +205,7 @@ mod tests {
 
     #[test]
     fn test_decode_implied() {
-        let program_rom = ROM::new(Vec::new());
+        let program_rom = ROM::new(vec![0x00]);
         let mut cpu = prepare_cpu(program_rom);
         let opcode = Opcode {
             base_name: OpcodeBaseName::TXA,
@@ -176,7 +219,7 @@ mod tests {
 
     #[test]
     fn test_decode_accumulator() {
-        let program_rom = ROM::new(Vec::new());
+        let program_rom = ROM::new(vec![0x00]);
         let mut cpu = prepare_cpu(program_rom);
         let opcode = Opcode {
             base_name: OpcodeBaseName::ASL,
@@ -288,7 +331,8 @@ mod tests {
 
     #[test]
     fn test_decode_absolute_x() {
-        let program_rom_data = vec![0x01, 0x02, 0xFF, 0x00, 0x02, 0xFF];
+        let mut program_rom_data = vec![0x00; 0x8000];
+        program_rom_data[..6].copy_from_slice(&[0x01, 0x02, 0xFF, 0x00, 0x02, 0xFF]);
         let program_rom = ROM::new(program_rom_data);
         let mut cpu = prepare_cpu(program_rom);
         let opcode = Opcode {
@@ -320,7 +364,8 @@ mod tests {
 
     #[test]
     fn test_decode_absolute_y() {
-        let program_rom_data = vec![0x01, 0x02, 0xFF, 0x00, 0x02, 0xFF];
+        let mut program_rom_data = vec![0x00; 0x8000];
+        program_rom_data[..6].copy_from_slice(&[0x01, 0x02, 0xFF, 0x00, 0x02, 0xFF]);
         let program_rom = ROM::new(program_rom_data);
         let mut cpu = prepare_cpu(program_rom);
         let opcode = Opcode {
@@ -0,0 +1,281 @@
+use crate::{Byte, Word};
+
+use super::{
+    opcode::{self, Addressing, Opcode},
+    Bus, CPURegister, CpuVariant,
+};
+
+#[derive(Debug)]
+pub struct Instruction {
+    pub address: Word,
+    pub opcode: Opcode,
+    pub bytes: Vec<Byte>,
+    pub text: String,
+    // where the instruction reads, writes or jumps, when it can be known
+    pub effective_address: Option<Word>,
+}
+
+impl Instruction {
+    pub fn length(&self) -> usize {
+        self.bytes.len()
+    }
+    pub fn operand(&self) -> &[Byte] {
+        &self.bytes[1..]
+    }
+    pub fn is_unofficial(&self) -> bool {
+        opcode::is_unofficial(self.bytes[0])
+    }
+    // The text with the address written in the operand replaced by `name`,
+    // e.g. `JSR player_update` for `JSR $C4EF`.
+    pub fn text_with(&self, name: impl Fn(Word) -> Option<String>) -> String {
+        let operand = self.operand();
+        let (address, literal) = match self.opcode.addressing {
+            Addressing::ZeroPage
+            | Addressing::ZeroPageX
+            | Addressing::ZeroPageY
+            | Addressing::IndirectX
+            | Addressing::IndirectY => (operand[0] as Word, format!("${:02X}", operand[0])),
+            Addressing::Absolute
+            | Addressing::AbsoluteX
+            | Addressing::AbsoluteY
+            | Addressing::Indirect => {
+                let word = operand[0] as Word | (operand[1] as Word) << 8;
+                (word, format!("${:04X}", word))
+            }
+            Addressing::Relative => {
+                let target = self
+                    .address
+                    .wrapping_add(2)
+                    .wrapping_add(operand[0] as i8 as Word);
+                (target, format!("${:04X}", target))
+            }
+            _ => return self.text.clone(),
+        };
+        match name(address) {
+            Some(name) => self.text.replacen(&literal, &name, 1),
+            None => self.text.clone(),
+        }
+    }
+}
+
+pub fn length(addressing: &Addressing) -> Word {
+    match addressing {
+        Addressing::Implied | Addressing::Accumulator => 1,
+        Addressing::Absolute
+        | Addressing::AbsoluteX
+        | Addressing::AbsoluteY
+        | Addressing::Indirect => 3,
+        _ => 2,
+    }
+}
+
+// Decodes the instruction at `address` without side effects. Indexed and
+// indirect-indexed operands only get an effective address when the
+// registers are given.
+pub fn disassemble<B: Bus>(
+    bus: &B,
+    address: Word,
+    register: Option<&CPURegister>,
+    variant: CpuVariant,
+) -> Instruction {
+    let opcode = opcode::get_opcode(bus.peek(address));
+    let bytes = (0..length(&opcode.addressing))
+        .map(|i| bus.peek(address.wrapping_add(i)))
+        .collect::<Vec<_>>();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = byte as Word | (bytes.get(2).copied().unwrap_or(0) as Word) << 8;
+    let x = register.map(|register| register.get_x());
+    let y = register.map(|register| register.get_y());
+    let zero_page_word = |pointer: Byte| {
+        bus.peek(pointer as Word) as Word | (bus.peek(pointer.wrapping_add(1) as Word) as Word) << 8
+    };
+
+    let (operand, effective_address) = match opcode.addressing {
+        Addressing::Implied => (String::new(), None),
+        Addressing::Accumulator => ("A".to_string(), None),
+        Addressing::Immediate => (format!("#${:02X}", byte), None),
+        Addressing::ZeroPage => (format!("${:02X}", byte), Some(byte as Word)),
+        Addressing::ZeroPageX => (
+            format!("${:02X},X", byte),
+            x.map(|x| byte.wrapping_add(x) as Word),
+        ),
+        Addressing::ZeroPageY => (
+            format!("${:02X},Y", byte),
+            y.map(|y| byte.wrapping_add(y) as Word),
+        ),
+        Addressing::Relative => {
+            let target = address.wrapping_add(2).wrapping_add(byte as i8 as Word);
+            (format!("${:04X}", target), Some(target))
+        }
+        Addressing::Absolute => (format!("${:04X}", word), Some(word)),
+        Addressing::AbsoluteX => (
+            format!("${:04X},X", word),
+            x.map(|x| word.wrapping_add(x as Word)),
+        ),
+        Addressing::AbsoluteY => (
+            format!("${:04X},Y", word),
+            y.map(|y| word.wrapping_add(y as Word)),
+        ),
+        Addressing::Indirect => {
+            let hi = if variant.has_indirect_jump_bug() {
+                (word & 0xFF00) | (word as Byte).wrapping_add(1) as Word
+            } else {
+                word.wrapping_add(1)
+            };
+            let target = bus.peek(word) as Word | (bus.peek(hi) as Word) << 8;
+            (format!("(${:04X})", word), Some(target))
+        }
+        Addressing::IndirectX => (
+            format!("(${:02X},X)", byte),
+            x.map(|x| zero_page_word(byte.wrapping_add(x))),
+        ),
+        Addressing::IndirectY => (
+            format!("(${:02X}),Y", byte),
+            y.map(|y| zero_page_word(byte).wrapping_add(y as Word)),
+        ),
+    };
+
+    let mnemonic = opcode.base_name.mnemonic();
+    let text = if operand.is_empty() {
+        mnemonic
+    } else {
+        format!("{} {}", mnemonic, operand)
+    };
+    Instruction {
+        address,
+        opcode,
+        bytes,
+        text,
+        effective_address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::opcode::OpcodeBaseName;
+
+    struct ROMBus {
+        memory: Vec<Byte>,
+    }
+    impl Bus for ROMBus {
+        fn read(&mut self, address: Word) -> Byte {
+            self.memory[address as usize]
+        }
+        fn peek(&self, address: Word) -> Byte {
+            self.memory[address as usize]
+        }
+        fn write(&mut self, _address: Word, _data: Byte) -> () {}
+        fn tick(&mut self) -> () {}
+    }
+
+    fn prepare_bus(program: &[Byte]) -> ROMBus {
+        let mut memory = vec![0x00; 0x10000];
+        memory[0x8000..0x8000 + program.len()].copy_from_slice(program);
+        ROMBus { memory }
+    }
+
+    #[test]
+    fn test_all_opcodes() {
+        for byte in 0x00..=0xFF {
+            let bus = prepare_bus(&[byte, 0x34, 0x12]);
+            let instruction = disassemble(&bus, 0x8000, None, CpuVariant::default());
+            let opcode = opcode::get_opcode(byte);
+            assert_eq!(instruction.length(), length(&opcode.addressing) as usize);
+            assert_eq!(instruction.bytes[0], byte);
+            assert!(instruction.text.starts_with(&opcode.base_name.mnemonic()));
+        }
+    }
+
+    #[test]
+    fn test_text() {
+        let cases: [(&[Byte], &str); 13] = [
+            (&[0xE8], "INX"),
+            (&[0x4A], "LSR A"),
+            (&[0xA9, 0x10], "LDA #$10"),
+            (&[0xA5, 0x10], "LDA $10"),
+            (&[0xB5, 0x10], "LDA $10,X"),
+            (&[0xB6, 0x10], "LDX $10,Y"),
+            (&[0xD0, 0xFE], "BNE $8000"),
+            (&[0x20, 0x34, 0x12], "JSR $1234"),
+            (&[0xBD, 0x34, 0x12], "LDA $1234,X"),
+            (&[0xB9, 0x34, 0x12], "LDA $1234,Y"),
+            (&[0x6C, 0x34, 0x12], "JMP ($1234)"),
+            (&[0xA1, 0x10], "LDA ($10,X)"),
+            (&[0xB3, 0x10], "LAX ($10),Y"),
+        ];
+        for (program, text) in cases {
+            let instruction =
+                disassemble(&prepare_bus(program), 0x8000, None, CpuVariant::default());
+            assert_eq!(instruction.text, text);
+            assert_eq!(instruction.operand(), &program[1..]);
+        }
+        let instruction = disassemble(
+            &prepare_bus(&[0x04, 0x10]),
+            0x8000,
+            None,
+            CpuVariant::default(),
+        );
+        assert_eq!(instruction.opcode.base_name, OpcodeBaseName::NOPD);
+        assert_eq!(instruction.text, "NOP $10");
+        assert_eq!(instruction.is_unofficial(), true);
+    }
+
+    #[test]
+    fn test_effective_address() {
+        let mut bus = prepare_bus(&[0xB1, 0x10, 0xBD, 0xFF, 0x12, 0x6C, 0xFF, 0x02, 0x90, 0x80]);
+        bus.memory[0x0010] = 0x00;
+        bus.memory[0x0011] = 0x03;
+        bus.memory[0x02FF] = 0x34;
+        bus.memory[0x0200] = 0x12;
+        bus.memory[0x0300] = 0x56;
+        let variant = CpuVariant::default();
+
+        // indexed operands need the registers
+        assert_eq!(
+            disassemble(&bus, 0x8000, None, variant).effective_address,
+            None
+        );
+        let mut register = CPURegister::default();
+        register.set_x(0x01);
+        register.set_y(0x05);
+        let instruction = disassemble(&bus, 0x8000, Some(&register), variant);
+        assert_eq!(instruction.effective_address, Some(0x0305));
+        let instruction = disassemble(&bus, 0x8002, Some(&register), variant);
+        assert_eq!(instruction.effective_address, Some(0x1300));
+
+        // JMP ($02FF) wraps within the page on NMOS parts
+        let instruction = disassemble(&bus, 0x8005, None, variant);
+        assert_eq!(instruction.effective_address, Some(0x1234));
+        let instruction = disassemble(&bus, 0x8005, None, CpuVariant::CMOS65C02);
+        assert_eq!(instruction.effective_address, Some(0x5634));
+
+        // branches resolve their target relative to the next instruction
+        let instruction = disassemble(&bus, 0x8008, None, variant);
+        assert_eq!(instruction.text, "BCC $7F8A");
+        assert_eq!(instruction.effective_address, Some(0x7F8A));
+    }
+
+    #[test]
+    fn test_text_with() {
+        let name = |address: Word| match address {
+            0x0010 => Some("pointer".to_string()),
+            0x8000 => Some("loop".to_string()),
+            0x1234 => Some("table".to_string()),
+            _ => None,
+        };
+        let cases: [(&[Byte], &str); 6] = [
+            (&[0xB1, 0x10], "LDA (pointer),Y"),
+            (&[0xD0, 0xFE], "BNE loop"),
+            (&[0xBD, 0x34, 0x12], "LDA table,X"),
+            (&[0x6C, 0x34, 0x12], "JMP (table)"),
+            (&[0xA9, 0x10], "LDA #$10"),
+            (&[0xAD, 0x35, 0x12], "LDA $1235"),
+        ];
+        for (program, text) in cases {
+            let instruction =
+                disassemble(&prepare_bus(program), 0x8000, None, CpuVariant::default());
+            assert_eq!(instruction.text_with(name), text);
+        }
+    }
+}
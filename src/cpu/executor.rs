@@ -1,11 +1,12 @@
-use crate::{ppu::PPU, Byte, Word};
+use crate::{Byte, Word};
 
 use super::{
     opcode::{Addressing, Opcode, OpcodeBaseName},
-    CPU,
+    register::CPURegister,
+    Bus, FrameKind, CPU,
 };
 
-pub fn execute<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+pub fn execute<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     match opcode.base_name {
         OpcodeBaseName::LDA => execute_lda(cpu, opcode, operand),
         OpcodeBaseName::LDX => execute_ldx(cpu, opcode, operand),
@@ -64,8 +65,8 @@ pub fn execute<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
         OpcodeBaseName::BRK => execute_brk(cpu, opcode, operand),
         OpcodeBaseName::NOP => {}
 
-        OpcodeBaseName::NOPD => {}
-        OpcodeBaseName::NOPI => {}
+        OpcodeBaseName::NOPD => execute_nop_read(cpu, opcode, operand),
+        OpcodeBaseName::NOPI => execute_nop_read(cpu, opcode, operand),
         OpcodeBaseName::LAX => execute_lax(cpu, opcode, operand),
         OpcodeBaseName::SAX => execute_sax(cpu, opcode, operand),
         OpcodeBaseName::DCP => execute_dcp(cpu, opcode, operand),
@@ -74,10 +75,22 @@ pub fn execute<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
         OpcodeBaseName::RLA => execute_rla(cpu, opcode, operand),
         OpcodeBaseName::SRE => execute_sre(cpu, opcode, operand),
         OpcodeBaseName::RRA => execute_rra(cpu, opcode, operand),
+        OpcodeBaseName::ANC => execute_anc(cpu, opcode, operand),
+        OpcodeBaseName::ALR => execute_alr(cpu, opcode, operand),
+        OpcodeBaseName::ARR => execute_arr(cpu, opcode, operand),
+        OpcodeBaseName::AXS => execute_axs(cpu, opcode, operand),
+        OpcodeBaseName::XAA => execute_xaa(cpu, opcode, operand),
+        OpcodeBaseName::LXA => execute_lxa(cpu, opcode, operand),
+        OpcodeBaseName::LAS => execute_las(cpu, opcode, operand),
+        OpcodeBaseName::SHX => execute_shx(cpu, opcode, operand),
+        OpcodeBaseName::SHY => execute_shy(cpu, opcode, operand),
+        OpcodeBaseName::TAS => execute_tas(cpu, opcode, operand),
+        OpcodeBaseName::AHX => execute_ahx(cpu, opcode, operand),
+        OpcodeBaseName::JAM => execute_jam(cpu, opcode, operand),
     }
 }
 
-fn execute_lda<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_lda<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -88,7 +101,7 @@ fn execute_lda<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     register.set_zn_by(value);
 }
 
-fn execute_ldx<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_ldx<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -98,7 +111,7 @@ fn execute_ldx<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     register.set_x(value);
     register.set_zn_by(value);
 }
-fn execute_ldy<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_ldy<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -108,63 +121,63 @@ fn execute_ldy<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     register.set_y(value);
     register.set_zn_by(value);
 }
-fn execute_sta<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_sta<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     let value = cpu.get_register().get_a();
     cpu.write(operand, value);
 }
-fn execute_stx<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_stx<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     let value = cpu.get_register().get_x();
     cpu.write(operand, value);
 }
-fn execute_sty<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_sty<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     let value = cpu.get_register().get_y();
     cpu.write(operand, value);
 }
-fn execute_tax<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_tax<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let value = cpu.get_register().get_a();
     let register = cpu.get_register();
     register.set_x(value);
     register.set_zn_by(value);
 }
-fn execute_tay<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_tay<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let value = cpu.get_register().get_a();
     let register = cpu.get_register();
     register.set_y(value);
     register.set_zn_by(value);
 }
-fn execute_tsx<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_tsx<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let value = cpu.get_register().get_s();
     let register = cpu.get_register();
     register.set_x(value);
     register.set_zn_by(value);
 }
-fn execute_txa<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_txa<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let value = cpu.get_register().get_x();
     let register = cpu.get_register();
     register.set_a(value);
     register.set_zn_by(value);
 }
-fn execute_txs<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_txs<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let value = cpu.get_register().get_x();
     let register = cpu.get_register();
     register.set_s(value);
 }
-fn execute_tya<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_tya<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let value = cpu.get_register().get_y();
     let register = cpu.get_register();
     register.set_a(value);
     register.set_zn_by(value);
 }
 
-fn execute_adc<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_adc<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
         cpu.read_byte(operand)
     };
-    cpu.get_register().add_a(value);
+    add(cpu, value);
 }
-fn execute_and<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_and<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -176,16 +189,16 @@ fn execute_and<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     register.set_a(result);
     register.set_zn_by(result);
 }
-fn execute_asl<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_asl<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     if opcode.addressing == Addressing::Accumulator {
         cpu.get_register().left_shift_a()
     } else {
-        let value = cpu.read_byte(operand);
-        let result = cpu.get_register().set_flag_by_left_shift(value);
-        cpu.write(operand, result);
+        read_modify_write(cpu, operand, |register, value| {
+            register.set_flag_by_left_shift(value)
+        });
     };
 }
-fn execute_bit<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bit<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     let value = cpu.read_byte(operand);
     let register = cpu.get_register();
     let result = register.get_a() & value;
@@ -205,7 +218,7 @@ fn execute_bit<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> ()
         register.clear_n();
     }
 }
-fn execute_cmp<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_cmp<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -215,7 +228,7 @@ fn execute_cmp<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     let a = register.get_a();
     register.cmp(a, value);
 }
-fn execute_cpx<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_cpx<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -225,7 +238,7 @@ fn execute_cpx<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     let x = register.get_x();
     register.cmp(x, value);
 }
-fn execute_cpy<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_cpy<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -235,27 +248,24 @@ fn execute_cpy<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     let y = register.get_y();
     register.cmp(y, value);
 }
-fn execute_dec<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
-    let value = cpu.read_byte(operand);
-    let result = value.wrapping_sub(1);
-    cpu.write(operand, result);
-    cpu.get_register().set_zn_by(result);
+fn execute_dec<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    read_modify_write(cpu, operand, decrement);
 }
-fn execute_dex<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_dex<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let register = cpu.get_register();
     let value = register.get_x();
     let result = value.wrapping_sub(1);
     register.set_x(result);
     register.set_zn_by(result);
 }
-fn execute_dey<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_dey<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let register = cpu.get_register();
     let value = register.get_y();
     let result = value.wrapping_sub(1);
     register.set_y(result);
     register.set_zn_by(result);
 }
-fn execute_eor<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_eor<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -267,36 +277,33 @@ fn execute_eor<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     register.set_a(result);
     register.set_zn_by(result);
 }
-fn execute_inc<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
-    let value = cpu.read_byte(operand);
-    let result = value.wrapping_add(1);
-    cpu.write(operand, result);
-    cpu.get_register().set_zn_by(result);
+fn execute_inc<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    read_modify_write(cpu, operand, increment);
 }
-fn execute_inx<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_inx<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let register = cpu.get_register();
     let value = register.get_x();
     let result = value.wrapping_add(1);
     register.set_x(result);
     register.set_zn_by(result);
 }
-fn execute_iny<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_iny<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let register = cpu.get_register();
     let value = register.get_y();
     let result = value.wrapping_add(1);
     register.set_y(result);
     register.set_zn_by(result);
 }
-fn execute_lsr<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_lsr<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     if opcode.addressing == Addressing::Accumulator {
         cpu.get_register().right_shift_a()
     } else {
-        let value = cpu.read_byte(operand);
-        let result = cpu.get_register().set_flag_by_right_shift(value);
-        cpu.write(operand, result);
+        read_modify_write(cpu, operand, |register, value| {
+            register.set_flag_by_right_shift(value)
+        });
     };
 }
-fn execute_ora<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_ora<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
@@ -308,182 +315,363 @@ fn execute_ora<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
     register.set_a(result);
     register.set_zn_by(result);
 }
-fn execute_rol<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_rol<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     if opcode.addressing == Addressing::Accumulator {
         cpu.get_register().left_rotate_a()
     } else {
-        let value = cpu.read_byte(operand);
-        let result = cpu.get_register().set_flag_by_left_rotate(value);
-        cpu.write(operand, result);
+        read_modify_write(cpu, operand, |register, value| {
+            register.set_flag_by_left_rotate(value)
+        });
     };
 }
-fn execute_ror<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_ror<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     if opcode.addressing == Addressing::Accumulator {
         cpu.get_register().right_rotate_a()
     } else {
-        let value = cpu.read_byte(operand);
-        let result = cpu.get_register().set_flag_by_right_rotate(value);
-        cpu.write(operand, result);
+        read_modify_write(cpu, operand, |register, value| {
+            register.set_flag_by_right_rotate(value)
+        });
     };
 }
-fn execute_sbc<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
+fn execute_sbc<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     let value = if opcode.addressing == Addressing::Immediate {
         operand as Byte
     } else {
         cpu.read_byte(operand)
     };
-    cpu.get_register().sub_a(value);
+    subtract(cpu, value);
 }
 
-fn execute_pha<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_pha<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     let a = cpu.get_register().get_a();
     cpu.push(a);
 }
-fn execute_php<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_php<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().set_b();
     cpu.get_register().set_r();
     cpu.push_status();
 }
-fn execute_pla<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_pla<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
+    cpu.read_stack();
     let value = cpu.pop();
     let register = cpu.get_register();
     register.set_a(value);
     register.set_zn_by(value);
 }
-fn execute_plp<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_plp<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
+    cpu.read_stack();
     cpu.get_register().set_r();
     cpu.pop_status();
 }
 
-fn execute_jmp<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_jmp<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     cpu.get_register().set_pc(operand);
 }
-fn execute_jsr<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
-    cpu.get_register().decrement_pc_byte();
-    cpu.push_pc();
+// the return address is pushed by the decoder, between the two operand fetches
+fn execute_jsr<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let return_address = cpu.get_register().get_pc();
     cpu.get_register().set_pc(operand);
+    cpu.enter_call(FrameKind::Subroutine, return_address);
 }
-fn execute_rts<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_rts<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
+    let s = cpu.get_register().get_s();
+    cpu.read_stack();
     cpu.pop_pc();
-    cpu.get_register().increment_pc_byte();
+    cpu.fetch_byte();
+    cpu.leave_call(s);
 }
-fn execute_rti<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_rti<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
+    let s = cpu.get_register().get_s();
+    cpu.read_stack();
     cpu.pop_status();
     cpu.pop_pc();
     cpu.get_register().set_r();
+    cpu.leave_call(s);
 }
 
-fn execute_bcc<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bcc<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if !cpu.get_register().get_c() {
         cpu.branch(operand);
     }
 }
-fn execute_bcs<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bcs<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if cpu.get_register().get_c() {
         cpu.branch(operand);
     }
 }
-fn execute_beq<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_beq<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if cpu.get_register().get_z() {
         cpu.branch(operand);
     }
 }
-fn execute_bmi<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bmi<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if cpu.get_register().get_n() {
         cpu.branch(operand);
     }
 }
-fn execute_bne<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bne<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if !cpu.get_register().get_z() {
         cpu.branch(operand);
     }
 }
-fn execute_bpl<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bpl<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if !cpu.get_register().get_n() {
         cpu.branch(operand);
     }
 }
-fn execute_bvc<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bvc<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if !cpu.get_register().get_v() {
         cpu.branch(operand);
     }
 }
-fn execute_bvs<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_bvs<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     if cpu.get_register().get_v() {
         cpu.branch(operand);
     }
 }
 
-fn execute_clc<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_clc<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().clear_c();
 }
-fn execute_cld<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_cld<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().clear_d();
 }
-fn execute_cli<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_cli<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().clear_i();
 }
-fn execute_clv<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_clv<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().clear_v();
 }
-fn execute_sec<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_sec<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().set_c();
 }
-fn execute_sed<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_sed<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().set_d();
 }
-fn execute_sei<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
+fn execute_sei<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
     cpu.get_register().set_i();
 }
 
-fn execute_brk<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, _operand: Word) -> () {
-    let interrupt = cpu.get_register().get_i();
+fn execute_brk<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
+    // skip the padding byte read by the decoder
     cpu.get_register().increment_pc_byte();
+    let return_address = cpu.get_register().get_pc();
     cpu.push_pc();
     cpu.get_register().set_b();
     cpu.get_register().set_r();
     cpu.push_status();
-    cpu.get_register().set_i();
-    if interrupt {
-        cpu.set_pc_by_irq();
-    }
-    cpu.get_register().decrement_pc_byte();
+    cpu.set_interrupt_disable();
+    cpu.set_pc_by_irq();
+    cpu.enter_call(FrameKind::IRQ, return_address);
 }
 
-fn execute_lax<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_lda(cpu, opcode, operand);
-    execute_ldx(cpu, opcode, operand);
+fn execute_lax<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let value = cpu.read_byte(operand);
+    let register = cpu.get_register();
+    register.set_a(value);
+    register.set_x(value);
+    register.set_zn_by(value);
 }
-fn execute_sax<P: PPU>(cpu: &mut CPU<P>, _opcode: &Opcode, operand: Word) -> () {
+fn execute_sax<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
     let register = cpu.get_register();
     let a = register.get_a();
     let x = register.get_x();
     let value = a & x;
     cpu.write(operand, value);
 }
-fn execute_isb<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_inc(cpu, opcode, operand);
-    execute_sbc(cpu, opcode, operand);
+fn execute_isb<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let result = read_modify_write(cpu, operand, increment);
+    subtract(cpu, result);
+}
+fn execute_dcp<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let result = read_modify_write(cpu, operand, decrement);
+    let register = cpu.get_register();
+    let a = register.get_a();
+    register.cmp(a, result);
+}
+fn execute_slo<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let result = read_modify_write(cpu, operand, |register, value| {
+        register.set_flag_by_left_shift(value)
+    });
+    let register = cpu.get_register();
+    let a = register.get_a() | result;
+    register.set_a(a);
+    register.set_zn_by(a);
+}
+fn execute_rla<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let result = read_modify_write(cpu, operand, |register, value| {
+        register.set_flag_by_left_rotate(value)
+    });
+    let register = cpu.get_register();
+    let a = register.get_a() & result;
+    register.set_a(a);
+    register.set_zn_by(a);
+}
+fn execute_sre<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let result = read_modify_write(cpu, operand, |register, value| {
+        register.set_flag_by_right_shift(value)
+    });
+    let register = cpu.get_register();
+    let a = register.get_a() ^ result;
+    register.set_a(a);
+    register.set_zn_by(a);
 }
-fn execute_dcp<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_dec(cpu, opcode, operand);
-    execute_cmp(cpu, opcode, operand);
+fn execute_rra<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let result = read_modify_write(cpu, operand, |register, value| {
+        register.set_flag_by_right_rotate(value)
+    });
+    add(cpu, result);
 }
-fn execute_slo<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_asl(cpu, opcode, operand);
-    execute_ora(cpu, opcode, operand);
+
+// The 2A03 ignores the D flag, other variants add and subtract in BCD.
+fn add<B: Bus>(cpu: &mut CPU<B>, value: Byte) -> () {
+    let variant = cpu.variant();
+    let register = cpu.get_register();
+    if variant.has_decimal_mode() && register.get_d() {
+        register.add_a_decimal(value, variant.has_valid_decimal_flags());
+    } else {
+        register.add_a(value);
+    }
 }
-fn execute_rla<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_rol(cpu, opcode, operand);
+fn subtract<B: Bus>(cpu: &mut CPU<B>, value: Byte) -> () {
+    let variant = cpu.variant();
+    let register = cpu.get_register();
+    if variant.has_decimal_mode() && register.get_d() {
+        register.sub_a_decimal(value, variant.has_valid_decimal_flags());
+    } else {
+        register.sub_a(value);
+    }
+}
+
+// Read-modify-write instructions write the unmodified value back once
+// before writing the result.
+fn read_modify_write<B: Bus>(
+    cpu: &mut CPU<B>,
+    address: Word,
+    modify: impl FnOnce(&mut CPURegister, Byte) -> Byte,
+) -> Byte {
+    let value = cpu.read_byte(address);
+    cpu.write(address, value);
+    let result = modify(cpu.get_register(), value);
+    cpu.write(address, result);
+    result
+}
+fn increment(register: &mut CPURegister, value: Byte) -> Byte {
+    let result = value.wrapping_add(1);
+    register.set_zn_by(result);
+    result
+}
+fn decrement(register: &mut CPURegister, value: Byte) -> Byte {
+    let result = value.wrapping_sub(1);
+    register.set_zn_by(result);
+    result
+}
+fn execute_nop_read<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    // the operand is still read, side effects included
+    if opcode.addressing != Addressing::Immediate {
+        cpu.read_byte(operand);
+    }
+}
+fn execute_anc<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
     execute_and(cpu, opcode, operand);
+    let register = cpu.get_register();
+    if register.get_n() {
+        register.set_c();
+    } else {
+        register.clear_c();
+    }
 }
-fn execute_sre<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_lsr(cpu, opcode, operand);
-    execute_eor(cpu, opcode, operand);
+fn execute_alr<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    execute_and(cpu, opcode, operand);
+    cpu.get_register().right_shift_a();
 }
-fn execute_rra<P: PPU>(cpu: &mut CPU<P>, opcode: &Opcode, operand: Word) -> () {
-    execute_ror(cpu, opcode, operand);
-    execute_adc(cpu, opcode, operand);
+fn execute_arr<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    execute_and(cpu, opcode, operand);
+    let register = cpu.get_register();
+    register.right_rotate_a();
+    // carry and overflow come from bits 6 and 5 of the result
+    let result = register.get_a();
+    if result & 0b0100_0000 != 0 {
+        register.set_c();
+    } else {
+        register.clear_c();
+    }
+    if ((result >> 6) ^ (result >> 5)) & 0b1 != 0 {
+        register.set_v();
+    } else {
+        register.clear_v();
+    }
+}
+fn execute_axs<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let register = cpu.get_register();
+    let value = register.get_a() & register.get_x();
+    register.cmp(value, operand as Byte);
+    let result = value.wrapping_sub(operand as Byte);
+    register.set_x(result);
+}
+// XAA and LXA mix A with an unstable "magic" constant, $EE on most consoles.
+const MAGIC: Byte = 0xEE;
+fn execute_xaa<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let register = cpu.get_register();
+    let result = (register.get_a() | MAGIC) & register.get_x() & operand as Byte;
+    register.set_a(result);
+    register.set_zn_by(result);
+}
+fn execute_lxa<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let register = cpu.get_register();
+    let result = (register.get_a() | MAGIC) & operand as Byte;
+    register.set_a(result);
+    register.set_x(result);
+    register.set_zn_by(result);
+}
+fn execute_las<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, operand: Word) -> () {
+    let value = cpu.read_byte(operand);
+    let register = cpu.get_register();
+    let result = value & register.get_s();
+    register.set_a(result);
+    register.set_x(result);
+    register.set_s(result);
+    register.set_zn_by(result);
+}
+fn execute_shx<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    let value = cpu.get_register().get_x();
+    store_and_high(cpu, opcode, operand, value);
+}
+fn execute_shy<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    let value = cpu.get_register().get_y();
+    store_and_high(cpu, opcode, operand, value);
+}
+fn execute_tas<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    let register = cpu.get_register();
+    let value = register.get_a() & register.get_x();
+    register.set_s(value);
+    store_and_high(cpu, opcode, operand, value);
+}
+fn execute_ahx<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word) -> () {
+    let register = cpu.get_register();
+    let value = register.get_a() & register.get_x();
+    store_and_high(cpu, opcode, operand, value);
+}
+// The SH* family stores `value & (H + 1)`, H being the high byte of the
+// unindexed address. When indexing crosses a page the stored value also
+// replaces the high byte of the target address.
+fn store_and_high<B: Bus>(cpu: &mut CPU<B>, opcode: &Opcode, operand: Word, value: Byte) -> () {
+    let offset = if opcode.addressing == Addressing::AbsoluteX {
+        cpu.get_register().get_x()
+    } else {
+        cpu.get_register().get_y()
+    };
+    let base = operand.wrapping_sub(offset as Word);
+    let result = value & ((base >> 8) as Byte).wrapping_add(1);
+    let address = if base & 0xFF00 != operand & 0xFF00 {
+        ((result as Word) << 8) | (operand & 0x00FF)
+    } else {
+        operand
+    };
+    cpu.write(address, result);
+}
+fn execute_jam<B: Bus>(cpu: &mut CPU<B>, _opcode: &Opcode, _operand: Word) -> () {
+    cpu.jam();
 }
 
 #[cfg(test)]
@@ -492,7 +680,7 @@ mod tests {
 
     use crate::{
         controller::Controller,
-        cpu::CPUBus,
+        cpu::{CPUBus, CpuVariant},
         interrupt,
         ppu::{PPUBus, PPUImpl},
         rom::ROM,
@@ -500,16 +688,13 @@ mod tests {
 
     use super::*;
 
-    fn prepare_cpu() -> CPU<PPUImpl> {
+    fn prepare_cpu() -> CPU<CPUBus<PPUImpl>> {
         let interrupt = Rc::new(RefCell::new(interrupt::Interrupt::default()));
         let ppu_bus = PPUBus::new(ROM::new(vec![]), false);
         let ppu = Rc::new(RefCell::new(PPUImpl::new(ppu_bus, interrupt.clone())));
         let controller = Rc::new(RefCell::new(Controller::default()));
         let wram = Rc::new(RefCell::new(crate::cpu::WRAM::default()));
-        let dma = Rc::new(RefCell::new(crate::dma::DMA::new(
-            wram.clone(),
-            ppu.clone(),
-        )));
+        let dma = Rc::new(RefCell::new(crate::dma::DMA::default()));
         let cpu_bus = CPUBus::new(
             ROM::new(vec![]),
             wram.clone(),
@@ -857,4 +1042,214 @@ mod tests {
             assert_eq!(register.get_n(), true);
         }
     }
+
+    #[test]
+    fn test_anc() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::ANC,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        };
+        cpu.get_register().set_a(0xF0);
+        execute(&mut cpu, &opcode, 0x0081);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0x80);
+        assert_eq!(register.get_n(), true);
+        assert_eq!(register.get_c(), true);
+    }
+
+    #[test]
+    fn test_alr() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::ALR,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        };
+        cpu.get_register().set_a(0xFF);
+        execute(&mut cpu, &opcode, 0x0003);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0x01);
+        assert_eq!(register.get_c(), true);
+        assert_eq!(register.get_z(), false);
+    }
+
+    #[test]
+    fn test_arr() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::ARR,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        };
+        // (0xFF & 0xC0) ror with carry set -> 0xE0
+        cpu.get_register().set_a(0xFF);
+        cpu.get_register().set_c();
+        execute(&mut cpu, &opcode, 0x00C0);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0xE0);
+        assert_eq!(register.get_c(), true);
+        assert_eq!(register.get_v(), false);
+        assert_eq!(register.get_n(), true);
+
+        // (0xFF & 0x40) ror without carry -> 0x20
+        register.set_a(0xFF);
+        register.clear_c();
+        execute(&mut cpu, &opcode, 0x0040);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0x20);
+        assert_eq!(register.get_c(), false);
+        assert_eq!(register.get_v(), true);
+    }
+
+    #[test]
+    fn test_axs() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::AXS,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        };
+        cpu.get_register().set_a(0x0F);
+        cpu.get_register().set_x(0x3C);
+        execute(&mut cpu, &opcode, 0x000D);
+        let register = cpu.get_register();
+        assert_eq!(register.get_x(), 0xFF);
+        assert_eq!(register.get_c(), false);
+        assert_eq!(register.get_n(), true);
+    }
+
+    #[test]
+    fn test_las() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::LAS,
+            addressing: Addressing::AbsoluteY,
+            cycle: 4,
+        };
+        cpu.write(0x0010, 0x3C);
+        cpu.get_register().set_s(0xF0);
+        execute(&mut cpu, &opcode, 0x0010);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0x30);
+        assert_eq!(register.get_x(), 0x30);
+        assert_eq!(register.get_s(), 0x30);
+    }
+
+    #[test]
+    fn test_shx() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::SHX,
+            addressing: Addressing::AbsoluteY,
+            cycle: 5,
+        };
+        // base 0x0300 + y 0x10: stores x & 0x04
+        cpu.get_register().set_x(0xFF);
+        cpu.get_register().set_y(0x10);
+        execute(&mut cpu, &opcode, 0x0310);
+        assert_eq!(cpu.read_byte(0x0310), 0x04);
+
+        // base 0x02F8 + y 0x10 crosses into 0x0308, the high byte becomes x & 0x03
+        cpu.get_register().set_x(0x01);
+        execute(&mut cpu, &opcode, 0x0308);
+        assert_eq!(cpu.read_byte(0x0108), 0x01);
+    }
+
+    #[test]
+    fn test_jam() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::JAM,
+            addressing: Addressing::Implied,
+            cycle: 2,
+        };
+        execute(&mut cpu, &opcode, 0x0000);
+        assert_eq!(cpu.is_jammed(), true);
+        let pc = cpu.get_register().get_pc();
+        assert_eq!(cpu.run(), 1);
+        assert_eq!(cpu.get_register().get_pc(), pc);
+    }
+
+    #[test]
+    fn test_decimal_adc() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::ADC,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        };
+        // the 2A03 adds in binary even with D set
+        cpu.get_register().set_d();
+        cpu.get_register().set_a(0x58);
+        execute(&mut cpu, &opcode, 0x0046);
+        assert_eq!(cpu.get_register().get_a(), 0x9E);
+
+        cpu.set_variant(CpuVariant::NMOS6502);
+        cpu.get_register().clear_c();
+        cpu.get_register().set_a(0x58);
+        execute(&mut cpu, &opcode, 0x0046);
+        assert_eq!(cpu.get_register().get_a(), 0x04);
+        assert_eq!(cpu.get_register().get_c(), true);
+
+        cpu.get_register().set_a(0x12);
+        execute(&mut cpu, &opcode, 0x0034);
+        assert_eq!(cpu.get_register().get_a(), 0x47);
+        assert_eq!(cpu.get_register().get_c(), false);
+
+        // NMOS flags follow the binary sum 0x9A
+        cpu.get_register().set_a(0x99);
+        execute(&mut cpu, &opcode, 0x0001);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0x00);
+        assert_eq!(register.get_c(), true);
+        assert_eq!(register.get_z(), false);
+        assert_eq!(register.get_n(), true);
+
+        cpu.set_variant(CpuVariant::CMOS65C02);
+        cpu.get_register().clear_c();
+        cpu.get_register().set_a(0x99);
+        execute(&mut cpu, &opcode, 0x0001);
+        let register = cpu.get_register();
+        assert_eq!(register.get_a(), 0x00);
+        assert_eq!(register.get_c(), true);
+        assert_eq!(register.get_z(), true);
+        assert_eq!(register.get_n(), false);
+    }
+
+    #[test]
+    fn test_decimal_sbc() {
+        let mut cpu = prepare_cpu();
+        let opcode = Opcode {
+            base_name: OpcodeBaseName::SBC,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        };
+        for variant in [CpuVariant::NMOS6502, CpuVariant::CMOS65C02] {
+            cpu.set_variant(variant);
+            cpu.get_register().set_d();
+            cpu.get_register().set_c();
+            cpu.get_register().set_a(0x46);
+            execute(&mut cpu, &opcode, 0x0012);
+            assert_eq!(cpu.get_register().get_a(), 0x34);
+            assert_eq!(cpu.get_register().get_c(), true);
+
+            cpu.get_register().set_a(0x40);
+            execute(&mut cpu, &opcode, 0x0013);
+            assert_eq!(cpu.get_register().get_a(), 0x27);
+            assert_eq!(cpu.get_register().get_c(), true);
+
+            cpu.get_register().set_a(0x12);
+            execute(&mut cpu, &opcode, 0x0021);
+            assert_eq!(cpu.get_register().get_a(), 0x91);
+            assert_eq!(cpu.get_register().get_c(), false);
+
+            // the borrow comes out of the low digit
+            cpu.get_register().set_a(0x20);
+            execute(&mut cpu, &opcode, 0x0000);
+            assert_eq!(cpu.get_register().get_a(), 0x19);
+            assert_eq!(cpu.get_register().get_c(), true);
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{log, Byte, Cycle};
+use crate::{Byte, Cycle};
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum Addressing {
@@ -93,6 +93,86 @@ pub enum OpcodeBaseName {
     RLA,
     SRE,
     RRA,
+    ANC,
+    ALR,
+    ARR,
+    AXS,
+    XAA,
+    LXA,
+    LAS,
+    SHX,
+    SHY,
+    TAS,
+    AHX,
+    JAM,
+}
+
+impl OpcodeBaseName {
+    pub fn mnemonic(&self) -> String {
+        match self {
+            OpcodeBaseName::NOPD | OpcodeBaseName::NOPI => "NOP".to_string(),
+            _ => format!("{:?}", self),
+        }
+    }
+    // Instructions that write to their operand always spend the extra cycle
+    // of indexed addressing, whether or not a page is crossed.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            OpcodeBaseName::STA
+                | OpcodeBaseName::STX
+                | OpcodeBaseName::STY
+                | OpcodeBaseName::ASL
+                | OpcodeBaseName::DEC
+                | OpcodeBaseName::INC
+                | OpcodeBaseName::LSR
+                | OpcodeBaseName::ROL
+                | OpcodeBaseName::ROR
+                | OpcodeBaseName::SAX
+                | OpcodeBaseName::DCP
+                | OpcodeBaseName::ISB
+                | OpcodeBaseName::SLO
+                | OpcodeBaseName::RLA
+                | OpcodeBaseName::SRE
+                | OpcodeBaseName::RRA
+                | OpcodeBaseName::SHX
+                | OpcodeBaseName::SHY
+                | OpcodeBaseName::TAS
+                | OpcodeBaseName::AHX
+        )
+    }
+}
+
+// Opcodes outside the documented instruction set, including the extra
+// encodings of NOP and SBC.
+pub fn is_unofficial(byte: Byte) -> bool {
+    match get_opcode(byte).base_name {
+        OpcodeBaseName::NOP => byte != 0xEA,
+        OpcodeBaseName::SBC => byte == 0xEB,
+        OpcodeBaseName::NOPD
+        | OpcodeBaseName::NOPI
+        | OpcodeBaseName::LAX
+        | OpcodeBaseName::SAX
+        | OpcodeBaseName::DCP
+        | OpcodeBaseName::ISB
+        | OpcodeBaseName::SLO
+        | OpcodeBaseName::RLA
+        | OpcodeBaseName::SRE
+        | OpcodeBaseName::RRA
+        | OpcodeBaseName::ANC
+        | OpcodeBaseName::ALR
+        | OpcodeBaseName::ARR
+        | OpcodeBaseName::AXS
+        | OpcodeBaseName::XAA
+        | OpcodeBaseName::LXA
+        | OpcodeBaseName::LAS
+        | OpcodeBaseName::SHX
+        | OpcodeBaseName::SHY
+        | OpcodeBaseName::TAS
+        | OpcodeBaseName::AHX
+        | OpcodeBaseName::JAM => true,
+        _ => false,
+    }
 }
 
 #[derive(Debug)]
@@ -212,12 +292,12 @@ pub fn get_opcode(byte: Byte) -> Opcode {
         0x9D => Opcode {
             base_name: OpcodeBaseName::STA,
             addressing: Addressing::AbsoluteX,
-            cycle: 4,
+            cycle: 5,
         },
         0x99 => Opcode {
             base_name: OpcodeBaseName::STA,
             addressing: Addressing::AbsoluteY,
-            cycle: 4,
+            cycle: 5,
         },
         0x81 => Opcode {
             base_name: OpcodeBaseName::STA,
@@ -612,7 +692,7 @@ pub fn get_opcode(byte: Byte) -> Opcode {
         0x1E => Opcode {
             base_name: OpcodeBaseName::ASL,
             addressing: Addressing::AbsoluteX,
-            cycle: 6,
+            cycle: 7,
         },
         0x4A => Opcode {
             base_name: OpcodeBaseName::LSR,
@@ -662,7 +742,7 @@ pub fn get_opcode(byte: Byte) -> Opcode {
         0x3E => Opcode {
             base_name: OpcodeBaseName::ROL,
             addressing: Addressing::AbsoluteX,
-            cycle: 6,
+            cycle: 7,
         },
         0x6A => Opcode {
             base_name: OpcodeBaseName::ROR,
@@ -687,7 +767,7 @@ pub fn get_opcode(byte: Byte) -> Opcode {
         0x7E => Opcode {
             base_name: OpcodeBaseName::ROR,
             addressing: Addressing::AbsoluteX,
-            cycle: 6,
+            cycle: 7,
         },
         0xE8 => Opcode {
             base_name: OpcodeBaseName::INX,
@@ -891,62 +971,62 @@ pub fn get_opcode(byte: Byte) -> Opcode {
             cycle: 2,
         },
         0x02 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x12 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x22 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x32 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x42 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x52 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x62 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x72 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0x92 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0xB2 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0xD2 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
         0xF2 => Opcode {
-            base_name: OpcodeBaseName::NOP,
+            base_name: OpcodeBaseName::JAM,
             addressing: Addressing::Implied,
             cycle: 2,
         },
@@ -973,86 +1053,86 @@ pub fn get_opcode(byte: Byte) -> Opcode {
         0xE2 => Opcode {
             base_name: OpcodeBaseName::NOPD,
             addressing: Addressing::Immediate,
-            cycle: 3,
+            cycle: 2,
         },
         0x04 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPage,
             cycle: 3,
         },
         0x44 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPage,
             cycle: 3,
         },
         0x64 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPage,
             cycle: 3,
         },
         0x14 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPageX,
             cycle: 4,
         },
         0x34 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPageX,
             cycle: 4,
         },
         0x54 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPageX,
             cycle: 4,
         },
         0x74 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPageX,
             cycle: 4,
         },
         0xD4 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPageX,
             cycle: 4,
         },
         0xF4 => Opcode {
             base_name: OpcodeBaseName::NOPD,
-            addressing: Addressing::Implied,
+            addressing: Addressing::ZeroPageX,
             cycle: 4,
         },
         0x0C => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::Absolute,
             cycle: 4,
         },
         0x1C => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::AbsoluteX,
             cycle: 4,
         },
         0x3C => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::AbsoluteX,
             cycle: 4,
         },
         0x5C => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::AbsoluteX,
             cycle: 4,
         },
         0x7C => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::AbsoluteX,
             cycle: 4,
         },
         0xDC => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::AbsoluteX,
             cycle: 4,
         },
         0xFC => Opcode {
             base_name: OpcodeBaseName::NOPI,
-            addressing: Addressing::Implied,
+            addressing: Addressing::AbsoluteX,
             cycle: 4,
         },
         0xA7 => Opcode {
@@ -1320,9 +1400,70 @@ pub fn get_opcode(byte: Byte) -> Opcode {
             addressing: Addressing::IndirectY,
             cycle: 8,
         },
-        _ => {
-            log(&format!("Unknown opcode: {:02X}", byte));
-            panic!();
-        }
+        0x0B => Opcode {
+            base_name: OpcodeBaseName::ANC,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0x2B => Opcode {
+            base_name: OpcodeBaseName::ANC,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0x4B => Opcode {
+            base_name: OpcodeBaseName::ALR,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0x6B => Opcode {
+            base_name: OpcodeBaseName::ARR,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0xCB => Opcode {
+            base_name: OpcodeBaseName::AXS,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0x8B => Opcode {
+            base_name: OpcodeBaseName::XAA,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0xAB => Opcode {
+            base_name: OpcodeBaseName::LXA,
+            addressing: Addressing::Immediate,
+            cycle: 2,
+        },
+        0xBB => Opcode {
+            base_name: OpcodeBaseName::LAS,
+            addressing: Addressing::AbsoluteY,
+            cycle: 4,
+        },
+        0x9E => Opcode {
+            base_name: OpcodeBaseName::SHX,
+            addressing: Addressing::AbsoluteY,
+            cycle: 5,
+        },
+        0x9C => Opcode {
+            base_name: OpcodeBaseName::SHY,
+            addressing: Addressing::AbsoluteX,
+            cycle: 5,
+        },
+        0x9B => Opcode {
+            base_name: OpcodeBaseName::TAS,
+            addressing: Addressing::AbsoluteY,
+            cycle: 5,
+        },
+        0x9F => Opcode {
+            base_name: OpcodeBaseName::AHX,
+            addressing: Addressing::AbsoluteY,
+            cycle: 5,
+        },
+        0x93 => Opcode {
+            base_name: OpcodeBaseName::AHX,
+            addressing: Addressing::IndirectY,
+            cycle: 6,
+        },
     }
 }
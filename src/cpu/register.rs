@@ -1,4 +1,7 @@
-use crate::{Byte, Word};
+use crate::{
+    state::{StateReader, StateWriter},
+    Byte, Word,
+};
 
 #[derive(Debug)]
 pub struct CPURegister {
@@ -29,7 +32,7 @@ impl Default for CPURegister {
             a: 0x00,
             x: 0x00,
             y: 0x00,
-            s: 0xFD,
+            s: 0x00,
             p: CPUStatusRegister::default(),
             pc: 0x8000,
         }
@@ -128,6 +131,21 @@ impl CPURegister {
         }
         p
     }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        for data in [self.a, self.x, self.y, self.s, self.get_p()] {
+            state.write_u8(data);
+        }
+        state.write_u16(self.pc);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.a = state.read_u8()?;
+        self.x = state.read_u8()?;
+        self.y = state.read_u8()?;
+        self.s = state.read_u8()?;
+        self.set_p(state.read_u8()?);
+        self.pc = state.read_u16()?;
+        Ok(())
+    }
     pub fn set_p(&mut self, p: Byte) {
         self.p.c = (p & 0x01) != 0;
         self.p.z = (p & 0x02) != 0;
@@ -229,6 +247,61 @@ impl CPURegister {
         self.a = diff as u8;
         self.set_zn_by(self.a);
     }
+    // NMOS decimal mode leaves N, V and Z as the binary sum would, with N
+    // and V taken after the low digit is adjusted.
+    pub fn add_a_decimal(&mut self, value: Byte, has_valid_flags: bool) {
+        let carry = self.p.c as i16;
+        let mut lo = (self.a & 0x0F) as i16 + (value & 0x0F) as i16 + carry;
+        if lo >= 0x0A {
+            lo = ((lo + 0x06) & 0x0F) + 0x10;
+        }
+        let signed = (self.a & 0xF0) as i8 as i16 + (value & 0xF0) as i8 as i16 + lo;
+        let mut sum = (self.a & 0xF0) as i16 + (value & 0xF0) as i16 + lo;
+        self.p.z = (self.a as i16 + value as i16 + carry) as u8 == 0;
+        self.p.n = signed & 0x80 != 0;
+        self.p.v = !(-128..=127).contains(&signed);
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        self.p.c = sum >= 0x100;
+        self.a = sum as u8;
+        if has_valid_flags {
+            self.set_zn_by(self.a);
+        }
+    }
+    // C, V, N and Z follow the binary difference on NMOS parts.
+    pub fn sub_a_decimal(&mut self, value: Byte, has_valid_flags: bool) {
+        let a = self.a as i16;
+        let borrow = !self.p.c as i16;
+        let lo = (a & 0x0F) - (value & 0x0F) as i16 - borrow;
+        let result = if has_valid_flags {
+            let mut result = a - value as i16 - borrow;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if lo < 0 {
+                result -= 0x06;
+            }
+            result
+        } else {
+            let lo = if lo < 0 {
+                ((lo - 0x06) & 0x0F) - 0x10
+            } else {
+                lo
+            };
+            let result = (a & 0xF0) - (value & 0xF0) as i16 + lo;
+            if result < 0 {
+                result - 0x60
+            } else {
+                result
+            }
+        };
+        self.sub_a(value);
+        self.a = result as u8;
+        if has_valid_flags {
+            self.set_zn_by(self.a);
+        }
+    }
     pub fn left_shift_a(&mut self) {
         let result = self.set_flag_by_left_shift(self.a);
         self.a = result;
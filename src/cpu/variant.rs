@@ -0,0 +1,44 @@
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum CpuVariant {
+    // the NES CPU, a 6502 with the decimal mode circuitry cut out
+    #[default]
+    Ricoh2A03,
+    NMOS6502,
+    // only the 65C02 behaviors of the original instruction set,
+    // the added opcodes are not modeled
+    CMOS65C02,
+}
+
+// http://www.6502.org/tutorials/decimal_mode.html
+impl CpuVariant {
+    pub fn has_decimal_mode(&self) -> bool {
+        *self != CpuVariant::Ricoh2A03
+    }
+    // the NMOS parts leave N and Z from the binary result in decimal mode
+    pub fn has_valid_decimal_flags(&self) -> bool {
+        *self == CpuVariant::CMOS65C02
+    }
+    pub fn clears_decimal_on_interrupt(&self) -> bool {
+        *self == CpuVariant::CMOS65C02
+    }
+    // JMP ($xxFF) reads the high byte from $xx00 on NMOS parts
+    pub fn has_indirect_jump_bug(&self) -> bool {
+        *self != CpuVariant::CMOS65C02
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant() {
+        assert_eq!(CpuVariant::default(), CpuVariant::Ricoh2A03);
+        assert_eq!(CpuVariant::Ricoh2A03.has_decimal_mode(), false);
+        assert_eq!(CpuVariant::NMOS6502.has_decimal_mode(), true);
+        assert_eq!(CpuVariant::NMOS6502.has_valid_decimal_flags(), false);
+        assert_eq!(CpuVariant::CMOS65C02.has_valid_decimal_flags(), true);
+        assert_eq!(CpuVariant::Ricoh2A03.has_indirect_jump_bug(), true);
+        assert_eq!(CpuVariant::CMOS65C02.has_indirect_jump_bug(), false);
+    }
+}
@@ -0,0 +1,700 @@
+use std::fmt;
+
+use crate::{
+    cpu::{call_stack, opcode::OpcodeBaseName, Bus},
+    nes::NES,
+    symbols::Symbols,
+    trace::Tracer,
+    Byte, Word,
+};
+
+pub mod expression;
+
+use expression::{Context, Expression, Variable};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: Word,
+    pub data: Byte,
+    pub kind: AccessKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Space {
+    CPU,
+    // only accesses made through PPUDATA, not rendering fetches
+    PPU,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: Word,
+    pub condition: Option<Expression>,
+}
+
+pub struct Watchpoint {
+    pub id: usize,
+    pub space: Space,
+    pub start: Word,
+    pub end: Word,
+    pub kind: WatchKind,
+    pub condition: Option<Expression>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Step,
+    Breakpoint(usize),
+    Watchpoint(usize, MemoryAccess),
+    Scanline,
+    Frame,
+    Jammed,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Step => write!(f, "step"),
+            StopReason::Breakpoint(id) => write!(f, "breakpoint {}", id),
+            StopReason::Watchpoint(id, access) => write!(
+                f,
+                "watchpoint {} {:?} ${:04X} = {:02X}",
+                id, access.kind, access.address, access.data
+            ),
+            StopReason::Scanline => write!(f, "scanline"),
+            StopReason::Frame => write!(f, "frame"),
+            StopReason::Jammed => write!(f, "jammed"),
+        }
+    }
+}
+
+impl Watchpoint {
+    fn matches(&self, space: Space, access: &MemoryAccess) -> bool {
+        let is_kind = match self.kind {
+            WatchKind::Read => access.kind == AccessKind::Read,
+            WatchKind::Write => access.kind == AccessKind::Write,
+            WatchKind::ReadWrite => true,
+        };
+        self.space == space && is_kind && self.start <= access.address && access.address <= self.end
+    }
+}
+
+struct NESContext<'a> {
+    nes: &'a NES,
+    access: Option<MemoryAccess>,
+}
+
+impl Context for NESContext<'_> {
+    fn variable(&self, variable: Variable) -> i64 {
+        let cpu = self.nes.cpu();
+        let register = cpu.register();
+        match variable {
+            Variable::A => register.get_a() as i64,
+            Variable::X => register.get_x() as i64,
+            Variable::Y => register.get_y() as i64,
+            Variable::S => register.get_s() as i64,
+            Variable::P => register.get_p() as i64,
+            Variable::PC => register.get_pc() as i64,
+            Variable::Scanline => self.nes.ppu().scanline() as i64,
+            Variable::Dot => self.nes.ppu().dot() as i64,
+            Variable::Cycle => cpu.cycle() as i64,
+            Variable::Address => self.access.map_or(0, |access| access.address as i64),
+            Variable::Value => self.access.map_or(0, |access| access.data as i64),
+        }
+    }
+    fn peek(&self, address: Word) -> Byte {
+        self.nes.cpu().bus().peek(address)
+    }
+}
+
+// Runs a NES instruction by instruction, stopping on breakpoints and
+// watchpoints. Breakpoints stop before the instruction at their address
+// runs, watchpoints stop right after the instruction that made the access.
+pub struct Debugger {
+    nes: NES,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Symbols,
+    tracer: Option<Tracer>,
+    next_id: usize,
+}
+
+impl Debugger {
+    pub fn new(nes: NES) -> Self {
+        Debugger {
+            nes,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            symbols: Symbols::new(),
+            tracer: None,
+            next_id: 1,
+        }
+    }
+    pub fn nes(&self) -> &NES {
+        &self.nes
+    }
+    pub fn nes_mut(&mut self) -> &mut NES {
+        &mut self.nes
+    }
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+    // Adds the labels in a .dbg, .nl or .mlb file, picked by its name.
+    pub fn load_symbols(&mut self, file_name: &str, text: &str) -> Result<(), String> {
+        let program_rom_size = self.nes.cpu().bus().program_rom_size();
+        self.symbols.load(file_name, text, program_rom_size)
+    }
+    // Records every instruction run from here on, until taken back.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> () {
+        self.tracer = tracer;
+        self.update_access_logging();
+    }
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        let tracer = self.tracer.take();
+        self.update_access_logging();
+        tracer
+    }
+    // The routines by cycles spent in the last whole frame, or since
+    // profiling started.
+    pub fn profile_report(&self, is_session: bool) -> Option<String> {
+        let call_stack = self.nes.cpu().call_stack()?;
+        let profile = if is_session {
+            call_stack.session_profile()
+        } else {
+            call_stack.last_frame_profile()
+        };
+        Some(call_stack::report(profile, |address| self.name(address)))
+    }
+    // One line per frame, innermost first.
+    pub fn backtrace(&self) -> Option<String> {
+        let call_stack = self.nes.cpu().call_stack()?;
+        let lines = call_stack.frames().iter().rev().map(|frame| {
+            format!(
+                "{:?} {} from {}\n",
+                frame.kind,
+                self.name(frame.routine),
+                self.name(frame.return_address)
+            )
+        });
+        Some(lines.collect())
+    }
+    fn name(&self, address: Word) -> String {
+        self.symbols
+            .describe(address)
+            .unwrap_or_else(|| format!("${:04X}", address))
+    }
+    // The instruction at the address with its operand named by the labels.
+    pub fn disassemble(&self, address: Word) -> String {
+        self.nes
+            .cpu()
+            .disassemble(address)
+            .text_with(|address| self.symbols.describe(address))
+    }
+
+    pub fn add_breakpoint(
+        &mut self,
+        address: Word,
+        condition: Option<&str>,
+    ) -> Result<usize, String> {
+        let condition = condition.map(|text| self.parse(text)).transpose()?;
+        let id = self.take_id();
+        self.breakpoints.push(Breakpoint {
+            id,
+            address,
+            condition,
+        });
+        Ok(id)
+    }
+    pub fn add_watchpoint(
+        &mut self,
+        space: Space,
+        start: Word,
+        end: Word,
+        kind: WatchKind,
+        condition: Option<&str>,
+    ) -> Result<usize, String> {
+        let condition = condition.map(|text| self.parse(text)).transpose()?;
+        let id = self.take_id();
+        self.watchpoints.push(Watchpoint {
+            id,
+            space,
+            start,
+            end,
+            kind,
+            condition,
+        });
+        self.update_access_logging();
+        Ok(id)
+    }
+    // Adds a breakpoint at a label or address, e.g. `player_update+3`.
+    pub fn add_breakpoint_at(
+        &mut self,
+        location: &str,
+        condition: Option<&str>,
+    ) -> Result<usize, String> {
+        let address = self.evaluate(location)? as Word;
+        self.add_breakpoint(address, condition)
+    }
+    // Evaluates an expression against the current state, labels included.
+    pub fn evaluate(&self, text: &str) -> Result<i64, String> {
+        let context = NESContext {
+            nes: &self.nes,
+            access: None,
+        };
+        Ok(self.parse(text)?.evaluate(&context))
+    }
+    // Removes the breakpoint or watchpoint with the id.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|breakpoint| breakpoint.id != id);
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        self.update_access_logging();
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    pub fn step_into(&mut self) -> StopReason {
+        self.run_until(|_, _| true, StopReason::Step)
+    }
+    // Runs a whole subroutine when the next instruction is a JSR.
+    pub fn step_over(&mut self) -> StopReason {
+        let register = self.nes.cpu().register();
+        let (pc, s) = (register.get_pc(), register.get_s());
+        let instruction = self.nes.cpu().disassemble(pc);
+        if instruction.opcode.base_name != OpcodeBaseName::JSR {
+            return self.step_into();
+        }
+        let return_address = pc.wrapping_add(instruction.length() as Word);
+        // recursion comes back to the same address with a deeper stack
+        self.run_until(
+            move |nes, _| {
+                let register = nes.cpu().register();
+                register.get_pc() == return_address && register.get_s() == s
+            },
+            StopReason::Step,
+        )
+    }
+    // Runs until the current subroutine or interrupt handler returns.
+    pub fn step_out(&mut self) -> StopReason {
+        let s = self.nes.cpu().register().get_s();
+        let mut is_returning = false;
+        self.run_until(
+            move |nes, _| {
+                let has_returned = is_returning && nes.cpu().register().get_s() > s;
+                let register = nes.cpu().register();
+                let base_name = nes.cpu().disassemble(register.get_pc()).opcode.base_name;
+                is_returning = base_name == OpcodeBaseName::RTS || base_name == OpcodeBaseName::RTI;
+                has_returned
+            },
+            StopReason::Step,
+        )
+    }
+    // Runs until the PPU enters the scanline.
+    pub fn run_to_scanline(&mut self, scanline: u16) -> StopReason {
+        let mut previous = self.nes.ppu().scanline();
+        self.run_until(
+            move |nes, _| {
+                let current = nes.ppu().scanline();
+                let has_entered = current == scanline && previous != scanline;
+                previous = current;
+                has_entered
+            },
+            StopReason::Scanline,
+        )
+    }
+    // Runs ahead only when nothing can stop the frame halfway.
+    pub fn run_frame(&mut self) -> StopReason {
+        if self.nes.run_ahead() > 0
+            && self.breakpoints.is_empty()
+            && self.watchpoints.is_empty()
+            && self.tracer.is_none()
+        {
+            self.nes.frame();
+            return StopReason::Frame;
+        }
+        self.run_until(|_, is_frame_done| is_frame_done, StopReason::Frame)
+    }
+
+    // Steps until `is_done` holds after an instruction. The instruction at
+    // the starting PC never hits a breakpoint, so running again from one
+    // moves on.
+    fn run_until(
+        &mut self,
+        mut is_done: impl FnMut(&NES, bool) -> bool,
+        reason: StopReason,
+    ) -> StopReason {
+        // `is_done` sees the state before the first instruction as well
+        is_done(&self.nes, false);
+        loop {
+            if self.nes.cpu().is_jammed() {
+                return StopReason::Jammed;
+            }
+            if let Some(tracer) = &mut self.tracer {
+                let interrupt = self.nes.cpu_mut().service_interrupts();
+                tracer.record(&self.nes, interrupt, &self.symbols);
+            }
+            let is_frame_done = self.nes.step();
+            let cpu_accesses = self.nes.cpu_mut().bus_mut().take_accesses();
+            if let Some(tracer) = &mut self.tracer {
+                tracer.complete(&cpu_accesses);
+            }
+            if let Some(reason) = self.hit_watchpoint(cpu_accesses) {
+                return reason;
+            }
+            if is_done(&self.nes, is_frame_done) {
+                return reason;
+            }
+            if let Some(id) = self.hit_breakpoint() {
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.hit_breakpoint(id);
+                }
+                return StopReason::Breakpoint(id);
+            }
+        }
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let pc = self.nes.cpu().register().get_pc();
+        let context = NESContext {
+            nes: &self.nes,
+            access: None,
+        };
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| {
+                breakpoint.address == pc
+                    && breakpoint
+                        .condition
+                        .as_ref()
+                        .is_none_or(|condition| condition.is_true(&context))
+            })
+            .map(|breakpoint| breakpoint.id)
+    }
+
+    fn hit_watchpoint(&mut self, cpu_accesses: Vec<MemoryAccess>) -> Option<StopReason> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        let ppu_accesses = self.nes.ppu_mut().take_accesses();
+        let accesses = cpu_accesses
+            .into_iter()
+            .map(|access| (Space::CPU, access))
+            .chain(ppu_accesses.into_iter().map(|access| (Space::PPU, access)));
+        for (space, access) in accesses {
+            let context = NESContext {
+                nes: &self.nes,
+                access: Some(access),
+            };
+            let hit = self.watchpoints.iter().find(|watchpoint| {
+                watchpoint.matches(space, &access)
+                    && watchpoint
+                        .condition
+                        .as_ref()
+                        .is_none_or(|condition| condition.is_true(&context))
+            });
+            if let Some(watchpoint) = hit {
+                return Some(StopReason::Watchpoint(watchpoint.id, access));
+            }
+        }
+        None
+    }
+
+    // Watchpoints look at every access, the tracer at the CPU ones.
+    fn update_access_logging(&mut self) -> () {
+        let is_watching = !self.watchpoints.is_empty();
+        let is_logging = is_watching || self.tracer.is_some();
+        self.nes.cpu_mut().bus_mut().set_access_logging(is_logging);
+        self.nes.ppu_mut().set_access_logging(is_watching);
+    }
+    fn parse(&self, text: &str) -> Result<Expression, String> {
+        Expression::parse_with(text, |name| {
+            self.symbols.address(name).map(|address| address as i64)
+        })
+    }
+    fn take_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::TraceFilter;
+
+    // $C000: LDX #$00
+    // $C002: JSR $C010
+    // $C005: STA $0300
+    // $C008: INX
+    // $C009: JMP $C002
+    // $C010: LDA #$42; JSR $C020; RTS
+    // $C020: INC $0301; RTS
+    fn prepare_debugger(program: &[(Word, &[Byte])]) -> Debugger {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        for (address, bytes) in program {
+            let offset = 0x10 + (*address - 0xC000) as usize;
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        // reset vector
+        data[0x10 + 0x3FFC] = 0x00;
+        data[0x10 + 0x3FFD] = 0xC0;
+        Debugger::new(NES::new(&data))
+    }
+
+    fn prepare_subroutines() -> Debugger {
+        prepare_debugger(&[
+            (
+                0xC000,
+                &[
+                    0xA2, 0x00, 0x20, 0x10, 0xC0, 0x8D, 0x00, 0x03, 0xE8, 0x4C, 0x02, 0xC0,
+                ],
+            ),
+            (0xC010, &[0xA9, 0x42, 0x20, 0x20, 0xC0, 0x60]),
+            (0xC020, &[0xEE, 0x01, 0x03, 0x60]),
+        ])
+    }
+
+    fn pc(debugger: &Debugger) -> Word {
+        debugger.nes().cpu().register().get_pc()
+    }
+
+    #[test]
+    fn test_step() {
+        let mut debugger = prepare_subroutines();
+        assert_eq!(pc(&debugger), 0xC000);
+        assert_eq!(debugger.step_into(), StopReason::Step);
+        assert_eq!(pc(&debugger), 0xC002);
+
+        // stepping over the JSR runs both nested subroutines
+        assert_eq!(debugger.step_over(), StopReason::Step);
+        assert_eq!(pc(&debugger), 0xC005);
+        assert_eq!(debugger.nes().cpu().bus().peek(0x0301), 0x01);
+        // anything else is a single step
+        assert_eq!(debugger.step_over(), StopReason::Step);
+        assert_eq!(pc(&debugger), 0xC008);
+
+        debugger.step_into();
+        debugger.step_into();
+        debugger.step_into();
+        assert_eq!(pc(&debugger), 0xC010);
+        debugger.step_into();
+        debugger.step_into();
+        assert_eq!(pc(&debugger), 0xC020);
+        // stepping out of the inner subroutine stops in the outer one
+        assert_eq!(debugger.step_out(), StopReason::Step);
+        assert_eq!(pc(&debugger), 0xC015);
+        assert_eq!(debugger.step_out(), StopReason::Step);
+        assert_eq!(pc(&debugger), 0xC005);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut debugger = prepare_subroutines();
+        let id = debugger.add_breakpoint(0xC008, Some("X == 2")).unwrap();
+        assert_eq!(debugger.run_frame(), StopReason::Breakpoint(id));
+        assert_eq!(pc(&debugger), 0xC008);
+        assert_eq!(debugger.nes().cpu().register().get_x(), 0x02);
+
+        // running again moves past the breakpoint it stopped on
+        let other = debugger.add_breakpoint(0xC020, None).unwrap();
+        assert_eq!(debugger.run_frame(), StopReason::Breakpoint(other));
+        assert_eq!(debugger.remove(other), true);
+        assert_eq!(debugger.remove(other), false);
+
+        assert_eq!(
+            debugger.add_breakpoint(0xC000, Some("X ==")).err(),
+            Some("unexpected end of expression".to_string())
+        );
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let mut debugger = prepare_subroutines();
+        let id = debugger
+            .add_watchpoint(
+                Space::CPU,
+                0x0301,
+                0x0301,
+                WatchKind::Write,
+                Some("VALUE == 3"),
+            )
+            .unwrap();
+        let access = MemoryAccess {
+            address: 0x0301,
+            data: 0x03,
+            kind: AccessKind::Write,
+        };
+        assert_eq!(debugger.run_frame(), StopReason::Watchpoint(id, access));
+        // stops after the instruction that made the access
+        assert_eq!(pc(&debugger), 0xC023);
+
+        // a range of reads
+        debugger.remove(id);
+        let id = debugger
+            .add_watchpoint(Space::CPU, 0x0300, 0x03FF, WatchKind::Read, None)
+            .unwrap();
+        let access = MemoryAccess {
+            address: 0x0301,
+            data: 0x03,
+            kind: AccessKind::Read,
+        };
+        assert_eq!(debugger.run_frame(), StopReason::Watchpoint(id, access));
+    }
+
+    #[test]
+    fn test_ppu_watchpoint() {
+        // write $0F to $3F00 through PPUADDR and PPUDATA
+        let mut debugger = prepare_debugger(&[(
+            0xC000,
+            &[
+                0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA9, 0x0F, 0x8D, 0x07,
+                0x20, 0x4C, 0x0F, 0xC0,
+            ],
+        )]);
+        let id = debugger
+            .add_watchpoint(Space::PPU, 0x3F00, 0x3F1F, WatchKind::ReadWrite, None)
+            .unwrap();
+        let access = MemoryAccess {
+            address: 0x3F00,
+            data: 0x0F,
+            kind: AccessKind::Write,
+        };
+        assert_eq!(debugger.run_frame(), StopReason::Watchpoint(id, access));
+        assert_eq!(pc(&debugger), 0xC00F);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut debugger = prepare_subroutines();
+        debugger
+            .load_symbols(
+                "game.nl",
+                "$C000#main#\n$C010#update#\n$C020#count#\n$0301#counter#\n",
+            )
+            .unwrap();
+        assert_eq!(debugger.disassemble(0xC002), "JSR update");
+        assert_eq!(debugger.disassemble(0xC020), "INC counter");
+
+        let id = debugger
+            .add_breakpoint_at("update+2", Some("[counter] == 1"))
+            .unwrap();
+        assert_eq!(debugger.run_frame(), StopReason::Breakpoint(id));
+        assert_eq!(pc(&debugger), 0xC012);
+        assert_eq!(debugger.nes().cpu().bus().peek(0x0301), 0x01);
+        assert_eq!(
+            debugger.add_breakpoint_at("draw", None).err(),
+            Some("unknown name: draw".to_string())
+        );
+        assert!(debugger.load_symbols("game.txt", "").is_err());
+    }
+
+    #[test]
+    fn test_trace_after_breakpoint() {
+        let mut debugger = prepare_subroutines();
+        let mut tracer = Tracer::with_capacity(4);
+        let id = debugger.add_breakpoint(0xC020, None).unwrap();
+        tracer.add_filter(TraceFilter::AfterBreakpoint(id));
+        debugger.set_tracer(Some(tracer));
+        assert_eq!(debugger.run_frame(), StopReason::Breakpoint(id));
+        assert_eq!(debugger.tracer().unwrap().entries().count(), 0);
+
+        debugger.remove(id);
+        debugger.step_into();
+        debugger.step_into();
+        let pcs = debugger
+            .tracer()
+            .unwrap()
+            .entries()
+            .map(|entry| entry.pc)
+            .collect::<Vec<_>>();
+        assert_eq!(pcs, [0xC020, 0xC023]);
+        assert!(debugger.take_tracer().is_some());
+        assert!(debugger.tracer().is_none());
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut debugger = prepare_subroutines();
+        debugger
+            .load_symbols("game.nl", "$C000#main#\n$C010#update#\n")
+            .unwrap();
+        assert_eq!(debugger.profile_report(false), None);
+        debugger.nes_mut().cpu_mut().set_call_tracking(true);
+        // into the inner subroutine
+        for _ in 0..5 {
+            debugger.step_into();
+        }
+        assert_eq!(
+            debugger.backtrace().unwrap(),
+            "Subroutine update+16 from update+5\nSubroutine update from main+5\n"
+        );
+        debugger.run_frame();
+        debugger.run_frame();
+        let report = debugger.profile_report(false).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].starts_with("(root) "));
+        assert!(lines[2].starts_with("update "));
+        assert!(lines[3].starts_with("update+16 "));
+        assert!(debugger.profile_report(true).is_some());
+    }
+
+    #[test]
+    fn test_profile_with_run_ahead() {
+        let mut debugger = prepare_subroutines();
+        debugger.nes_mut().cpu_mut().set_call_tracking(true);
+        debugger.nes_mut().set_run_ahead(1);
+        debugger.run_frame();
+        debugger.run_frame();
+        // restoring after the hidden frame keeps what was profiled
+        let report = debugger.profile_report(true).unwrap();
+        assert!(report.lines().any(|line| line.starts_with("$C010 ")));
+        // and only the real frames count
+        let mut other = prepare_subroutines();
+        other.nes_mut().cpu_mut().set_call_tracking(true);
+        other.run_frame();
+        other.run_frame();
+        assert_eq!(Some(report), other.profile_report(true));
+        assert_eq!(debugger.profile_report(false), other.profile_report(false));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut debugger = prepare_subroutines();
+        assert_eq!(debugger.run_to_scanline(100), StopReason::Scanline);
+        assert_eq!(debugger.nes().ppu().scanline(), 100);
+        assert_eq!(debugger.run_frame(), StopReason::Frame);
+        assert_eq!(debugger.nes().ppu().scanline(), 0);
+
+        // a jammed CPU never gets anywhere
+        let mut debugger = prepare_debugger(&[(0xC000, &[0x02])]);
+        debugger.step_into();
+        assert_eq!(debugger.run_frame(), StopReason::Jammed);
+    }
+}
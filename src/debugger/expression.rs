@@ -0,0 +1,398 @@
+use std::fmt;
+
+use crate::{Byte, Word};
+
+// Conditions for breakpoints and watchpoints, e.g. `A == $10 && [$0300] > 5`.
+//
+// Numbers are decimal, `$` or `0x` prefixed hex, or `%` prefixed binary.
+// `[address]` reads a byte from CPU space without side effects. Other names
+// can be resolved to numbers by the caller, e.g. labels. Operators
+// bind like Rust: `+ -`, then `&`, `^`, `|`, comparisons, `&&` and `||`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(i64),
+    Variable(Variable),
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    A,
+    X,
+    Y,
+    S,
+    P,
+    PC,
+    Scanline,
+    Dot,
+    Cycle,
+    // the address and data of the access that hit a watchpoint
+    Address,
+    Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    BitAnd,
+    BitXor,
+    BitOr,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+pub trait Context {
+    fn variable(&self, variable: Variable) -> i64;
+    fn peek(&self, address: Word) -> Byte;
+}
+
+impl Variable {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "A" => Some(Variable::A),
+            "X" => Some(Variable::X),
+            "Y" => Some(Variable::Y),
+            "S" | "SP" => Some(Variable::S),
+            "P" => Some(Variable::P),
+            "PC" => Some(Variable::PC),
+            "SCANLINE" => Some(Variable::Scanline),
+            "DOT" => Some(Variable::Dot),
+            "CYCLE" => Some(Variable::Cycle),
+            "ADDRESS" => Some(Variable::Address),
+            "VALUE" => Some(Variable::Value),
+            _ => None,
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        Expression::parse_with(text, |_| None)
+    }
+    // Names that are not variables are looked up with `resolve`.
+    pub fn parse_with(text: &str, resolve: impl Fn(&str) -> Option<i64>) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            resolve: &resolve,
+        };
+        let expression = parser.parse_binary(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected {}", token)),
+        }
+    }
+
+    pub fn evaluate(&self, context: &impl Context) -> i64 {
+        match self {
+            Expression::Number(number) => *number,
+            Expression::Variable(variable) => context.variable(*variable),
+            Expression::Memory(address) => context.peek(address.evaluate(context) as Word) as i64,
+            Expression::Not(operand) => (operand.evaluate(context) == 0) as i64,
+            Expression::Negate(operand) => operand.evaluate(context).wrapping_neg(),
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(context);
+                // && and || short-circuit so `[...]` on the right is not read
+                match operator {
+                    Operator::And if left == 0 => return 0,
+                    Operator::Or if left != 0 => return 1,
+                    _ => {}
+                }
+                let right = right.evaluate(context);
+                match operator {
+                    Operator::Add => left.wrapping_add(right),
+                    Operator::Subtract => left.wrapping_sub(right),
+                    Operator::BitAnd => left & right,
+                    Operator::BitXor => left ^ right,
+                    Operator::BitOr => left | right,
+                    Operator::Equal => (left == right) as i64,
+                    Operator::NotEqual => (left != right) as i64,
+                    Operator::Less => (left < right) as i64,
+                    Operator::LessEqual => (left <= right) as i64,
+                    Operator::Greater => (left > right) as i64,
+                    Operator::GreaterEqual => (left >= right) as i64,
+                    Operator::And | Operator::Or => (right != 0) as i64,
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, context: &impl Context) -> bool {
+        self.evaluate(context) != 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+// For error messages, where running out of tokens is a token too.
+fn describe(token: Option<Token>) -> String {
+    token.map_or("end of expression".to_string(), |token| token.to_string())
+}
+
+const SYMBOLS: [&str; 18] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "&", "^", "|", "!", "(", ")", "[", "]",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (token, length) = if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            (Token::Symbol(symbol), symbol.len())
+        } else {
+            let length = rest
+                .find(|c: char| {
+                    !(c.is_ascii_alphanumeric() || c == '_' || c == '@' || c == '$' || c == '%')
+                })
+                .unwrap_or(rest.len())
+                .max(1);
+            let word = &rest[..length];
+            let token = if let Some(hex) = word.strip_prefix('$').or(word.strip_prefix("0x")) {
+                Token::Number(parse_number(hex, 16, word)?)
+            } else if let Some(binary) = word.strip_prefix('%') {
+                Token::Number(parse_number(binary, 2, word)?)
+            } else if word.starts_with(|c: char| c.is_ascii_digit()) {
+                Token::Number(parse_number(word, 10, word)?)
+            } else if word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '@') {
+                Token::Name(word.to_string())
+            } else {
+                return Err(format!("unexpected character: {}", word));
+            };
+            (token, length)
+        };
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_number(digits: &str, radix: u32, word: &str) -> Result<i64, String> {
+    i64::from_str_radix(digits, radix).map_err(|_| format!("invalid number: {}", word))
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    resolve: &'a dyn Fn(&str) -> Option<i64>,
+}
+
+// operators from the loosest binding up
+const PRECEDENCE: [&[(&str, Operator)]; 7] = [
+    &[("||", Operator::Or)],
+    &[("&&", Operator::And)],
+    &[
+        ("==", Operator::Equal),
+        ("!=", Operator::NotEqual),
+        ("<=", Operator::LessEqual),
+        (">=", Operator::GreaterEqual),
+        ("<", Operator::Less),
+        (">", Operator::Greater),
+    ],
+    &[("|", Operator::BitOr)],
+    &[("^", Operator::BitXor)],
+    &[("&", Operator::BitAnd)],
+    &[("+", Operator::Add), ("-", Operator::Subtract)],
+];
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(symbol)) => Some(symbol),
+            _ => None,
+        }
+    }
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            token => Err(format!("expected {} but found {}", symbol, describe(token))),
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expression, String> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(symbol) = self.peek_symbol() {
+            let Some((_, operator)) = PRECEDENCE[level].iter().find(|(s, _)| *s == symbol) else {
+                break;
+            };
+            self.position += 1;
+            let right = self.parse_binary(level + 1)?;
+            left = Expression::Binary(*operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expression::Number(number)),
+            Some(Token::Name(name)) => match Variable::from_name(&name) {
+                Some(variable) => Ok(Expression::Variable(variable)),
+                None => (self.resolve)(&name)
+                    .map(Expression::Number)
+                    .ok_or(format!("unknown name: {}", name)),
+            },
+            Some(Token::Symbol("!")) => Ok(Expression::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Symbol("-")) => Ok(Expression::Negate(Box::new(self.parse_unary()?))),
+            Some(Token::Symbol("(")) => {
+                let expression = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Symbol("[")) => {
+                let expression = self.parse_binary(0)?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(expression)))
+            }
+            token => Err(format!("unexpected {}", describe(token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    struct TestContext {
+        memory: Vec<Byte>,
+        reads: Cell<usize>,
+    }
+    impl Context for TestContext {
+        fn variable(&self, variable: Variable) -> i64 {
+            match variable {
+                Variable::A => 0x10,
+                Variable::X => 0x03,
+                Variable::PC => 0xC000,
+                _ => 0,
+            }
+        }
+        fn peek(&self, address: Word) -> Byte {
+            self.reads.set(self.reads.get() + 1);
+            self.memory[address as usize]
+        }
+    }
+
+    fn evaluate(text: &str) -> i64 {
+        let mut memory = vec![0x00; 0x10000];
+        memory[0x0300] = 0x06;
+        memory[0x0303] = 0x02;
+        let context = TestContext {
+            memory,
+            reads: Cell::new(0),
+        };
+        Expression::parse(text).unwrap().evaluate(&context)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("A == $10 && [$0300] > 5"), 1);
+        assert_eq!(evaluate("a == 0x11 || [$0300] > 6"), 0);
+        assert_eq!(evaluate("[$0300 + x]"), 0x02);
+        assert_eq!(evaluate("PC >= $C000 && PC < $C100"), 1);
+        assert_eq!(evaluate("1 + 2 == 3"), 1);
+        assert_eq!(evaluate("A & %11110000 == $10"), 1);
+        assert_eq!(evaluate("!(A == 16)"), 0);
+        assert_eq!(evaluate("-1 < 0"), 1);
+        assert_eq!(evaluate("A ^ $FF | 1"), 0xEF);
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let context = TestContext {
+            memory: vec![0x00; 0x10000],
+            reads: Cell::new(0),
+        };
+        let expression = Expression::parse("A != $10 && [$0300] == 0").unwrap();
+        assert_eq!(expression.is_true(&context), false);
+        assert_eq!(context.reads.get(), 0);
+    }
+
+    #[test]
+    fn test_parse_with() {
+        let resolve = |name: &str| match name {
+            "player_x" => Some(0x0300),
+            "@loop" => Some(0xC010),
+            _ => None,
+        };
+        assert_eq!(
+            Expression::parse_with("[player_x + 3] == 2", resolve),
+            Expression::parse("[$0300 + 3] == 2")
+        );
+        assert_eq!(
+            Expression::parse_with("PC == @loop", resolve),
+            Expression::parse("PC == $C010")
+        );
+        // registers win over labels with the same name
+        assert_eq!(
+            Expression::parse_with("a", |_| Some(1)),
+            Ok(Expression::Variable(Variable::A))
+        );
+        assert_eq!(
+            Expression::parse_with("enemy_x", resolve),
+            Err("unknown name: enemy_x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!(
+            Expression::parse("A =="),
+            Err("unexpected end of expression".to_string())
+        );
+        assert_eq!(
+            Expression::parse("B == 1"),
+            Err("unknown name: B".to_string())
+        );
+        assert_eq!(
+            Expression::parse("[$10"),
+            Err("expected ] but found end of expression".to_string())
+        );
+        assert_eq!(
+            Expression::parse("$XYZ"),
+            Err("invalid number: $XYZ".to_string())
+        );
+        assert_eq!(Expression::parse("1 2"), Err("unexpected 2".to_string()));
+        assert_eq!(
+            Expression::parse("(A == 1))"),
+            Err("unexpected )".to_string())
+        );
+        assert_eq!(Expression::parse("A == )"), Err("unexpected )".to_string()));
+        assert_eq!(
+            Expression::parse("(A == 1 X"),
+            Err("expected ) but found X".to_string())
+        );
+    }
+}
@@ -1,90 +1,136 @@
-use std::{
-    cell::RefCell,
-    fmt::{Debug, Formatter},
-    rc::Rc,
+use crate::{
+    state::{StateReader, StateWriter},
+    Byte, Cycle, Word,
 };
 
-use crate::{cpu::WRAM, ppu::PPU, Cycle};
+// the 256 get/put pairs after the halt
+const TRANSFER_CYCLES: Cycle = 512;
 
-pub struct DMA<P: PPU> {
-    wram: Rc<RefCell<WRAM>>,
-    ppu: Rc<RefCell<P>>,
-    ram_address: Option<u16>,
+// What a halted CPU cycle does for OAM DMA, carried out by the CPU bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DMACycle {
+    // the halt, or the alignment cycle after it
+    Halt,
+    // reads the byte at the address through the bus
+    Get(Word),
+    // writes the byte read last to OAM at the index past OAMADDR
+    Put(Byte, Byte),
 }
 
-impl<P: PPU> Debug for DMA<P> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("DMA")
-            .field("ram_address", &self.ram_address)
-            .finish()
-    }
+#[derive(Debug, Default)]
+pub struct DMA {
+    // the page written to $4014, until the CPU halts for it
+    page: Option<Byte>,
+    address: Word,
+    data: Byte,
+    remaining: Cycle,
 }
 
-impl<P: PPU> DMA<P> {
-    pub fn new(wram: Rc<RefCell<WRAM>>, ppu: Rc<RefCell<P>>) -> Self {
-        DMA {
-            wram,
-            ppu,
-            ram_address: None,
-        }
+impl DMA {
+    // Starts the pending transfer and returns how long it halts the CPU: one
+    // cycle, plus one more to align when the halt lands on an odd (put)
+    // cycle, before the 256 get/put pairs.
+    pub fn start(&mut self, is_odd_cycle: bool) -> Cycle {
+        let Some(page) = self.page.take() else {
+            return 0;
+        };
+        self.address = (page as Word) << 8;
+        self.remaining = TRANSFER_CYCLES + if is_odd_cycle { 2 } else { 1 };
+        self.remaining
     }
-    pub fn run(&mut self) -> Cycle {
-        match self.ram_address {
-            Some(ram_address) => {
-                for i in 0..=0xff {
-                    let data = self.wram.borrow().read(ram_address + i);
-                    self.ppu.borrow_mut().transfer_sprite(i as u8, data);
-                }
-                self.ram_address = None;
-                513
-            }
-            None => 0,
+    // The next cycle of the running transfer, None when there is none.
+    pub fn step(&mut self) -> Option<DMACycle> {
+        if self.remaining == 0 {
+            return None;
         }
+        self.remaining -= 1;
+        let cycle = if self.remaining >= TRANSFER_CYCLES {
+            DMACycle::Halt
+        } else if self.remaining % 2 == 1 {
+            DMACycle::Get(self.address)
+        } else {
+            let index = self.address as Byte;
+            self.address = self.address.wrapping_add(1);
+            DMACycle::Put(index, self.data)
+        };
+        Some(cycle)
+    }
+    // The byte a get cycle read, for the put cycle after it.
+    pub fn latch(&mut self, data: Byte) -> () {
+        self.data = data;
     }
     pub fn write(&mut self, data: u8) {
-        self.ram_address = Some((data as u16) << 8);
+        self.page = Some(data);
+    }
+    // A transfer runs to the end once started, so only the page is saved.
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bool(self.page.is_some());
+        state.write_u16((self.page.unwrap_or(0) as Word) << 8);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let is_pending = state.read_bool()?;
+        let address = state.read_u16()?;
+        // any page, read through the CPU bus
+        if address & 0xFF != 0 {
+            return Err(format!(
+                "save state has DMA address {} out of range",
+                address
+            ));
+        }
+        self.page = is_pending.then_some((address >> 8) as Byte);
+        self.remaining = 0;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::cpu::WRAM;
-    use crate::ppu::MockPPU;
-    use mockall::predicate;
 
     #[test]
     fn test_dma() {
-        let wram = Rc::new(RefCell::new(WRAM::default()));
-        for i in 0..=0xff {
-            wram.borrow_mut().write(i, i as u8);
-        }
-        for i in 0..=0xff {
-            wram.borrow_mut().write(i + 0x100, (255 - i) as u8);
-        }
-        let ppu = Rc::new(RefCell::new(MockPPU::new()));
-        let mut dma = DMA::new(wram.clone(), ppu.clone());
+        let mut dma = DMA::default();
+        assert_eq!(dma.start(false), 0);
+        assert_eq!(dma.step(), None);
 
-        assert_eq!(dma.run(), 0);
+        dma.write(0x02);
+        assert_eq!(dma.start(false), 513);
+        assert_eq!(dma.start(false), 0);
+        assert_eq!(dma.step(), Some(DMACycle::Halt));
+        for i in 0..=0xFF {
+            assert_eq!(dma.step(), Some(DMACycle::Get(0x0200 + i)));
+            dma.latch(0xFF - i as Byte);
+            assert_eq!(dma.step(), Some(DMACycle::Put(i as Byte, 0xFF - i as Byte)));
+        }
+        assert_eq!(dma.step(), None);
 
-        dma.write(0x00);
-        for i in 0..=255 {
-            ppu.borrow_mut()
-                .expect_transfer_sprite()
-                .with(predicate::eq(i), predicate::eq(i))
-                .return_const(());
+        // any page, with the alignment cycle on an odd cycle
+        dma.write(0xFF);
+        assert_eq!(dma.start(true), 514);
+        assert_eq!(dma.step(), Some(DMACycle::Halt));
+        assert_eq!(dma.step(), Some(DMACycle::Halt));
+        assert_eq!(dma.step(), Some(DMACycle::Get(0xFF00)));
+        for _ in 0..509 {
+            dma.step();
         }
-        assert_eq!(dma.run(), 513);
+        assert_eq!(dma.step(), Some(DMACycle::Get(0xFFFF)));
+        assert_eq!(dma.step(), Some(DMACycle::Put(0xFF, 0x00)));
+        assert_eq!(dma.step(), None);
+    }
 
-        assert_eq!(dma.run(), 0);
+    #[test]
+    fn test_state() {
+        let mut dma = DMA::default();
+        dma.write(0x80);
+        let mut writer = StateWriter::new(0);
+        dma.save_state(&mut writer);
+        let data = writer.into_bytes();
 
-        dma.write(0x01);
-        for i in 0..=255 {
-            ppu.borrow_mut()
-                .expect_transfer_sprite()
-                .with(predicate::eq(i), predicate::eq((255 - i) as u8))
-                .return_const(());
-        }
-        assert_eq!(dma.run(), 513);
+        let mut other = DMA::default();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(other.load_state(&mut reader), Ok(()));
+        assert_eq!(other.start(false), 513);
+        assert_eq!(other.step(), Some(DMACycle::Halt));
+        assert_eq!(other.step(), Some(DMACycle::Get(0x8000)));
     }
 }
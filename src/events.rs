@@ -0,0 +1,200 @@
+use std::fmt;
+
+use crate::{renderer::Color, Byte, Cycle, Word};
+
+const DOTS_PER_LINE: usize = 341;
+// around the visible picture
+const BACKGROUND_COLOR: Color = (0x20, 0x20, 0x20);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    // a CPU write to $2000-$2007, $4014 or a mapper register at $8000-$FFFF;
+    // mirrors of the PPU registers are folded onto $2000-$2007
+    Write(Word, Byte),
+    // taken by the CPU
+    NMI,
+    IRQ,
+    SpriteZeroHit,
+}
+
+impl EventKind {
+    fn color(&self) -> Color {
+        match self {
+            EventKind::Write(0x2000, _) => (0xFF, 0x40, 0x40),
+            EventKind::Write(0x2001, _) => (0xFF, 0xA0, 0x20),
+            EventKind::Write(0x2003 | 0x2004, _) => (0xC0, 0x80, 0xFF),
+            EventKind::Write(0x2005, _) => (0x40, 0xFF, 0x40),
+            EventKind::Write(0x2006, _) => (0x40, 0x80, 0xFF),
+            EventKind::Write(0x2007, _) => (0x40, 0xFF, 0xFF),
+            EventKind::Write(0x4014, _) => (0xFF, 0x80, 0xC0),
+            EventKind::Write(0x2000..=0x2007, _) => (0xA0, 0xA0, 0xA0),
+            EventKind::Write(_, _) => (0xFF, 0xFF, 0xFF),
+            EventKind::NMI => (0xFF, 0x00, 0xFF),
+            EventKind::IRQ => (0xC0, 0x60, 0x00),
+            EventKind::SpriteZeroHit => (0xFF, 0xFF, 0x00),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub scanline: u16,
+    pub dot: Cycle,
+}
+
+// `SL:12 DOT:300 $2005=10`
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SL:{} DOT:{} ", self.scanline, self.dot)?;
+        match self.kind {
+            EventKind::Write(address, data) => write!(f, "${:04X}={:02X}", address, data),
+            kind => write!(f, "{:?}", kind),
+        }
+    }
+}
+
+// What happened where in the frame, for finding raster effects. A frame runs
+// from scanline 0 to the end of the pre-render line, like the picture.
+#[derive(Default)]
+pub struct EventLog {
+    frame: Vec<Event>,
+    last_frame: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog::default()
+    }
+    pub fn record(&mut self, kind: EventKind, scanline: u16, dot: Cycle) -> () {
+        self.frame.push(Event {
+            kind,
+            scanline,
+            dot,
+        });
+    }
+    pub fn end_frame(&mut self) -> () {
+        self.last_frame = std::mem::take(&mut self.frame);
+    }
+    // So far in the running frame.
+    pub fn events(&self) -> &[Event] {
+        &self.frame
+    }
+    pub fn last_frame(&self) -> &[Event] {
+        &self.last_frame
+    }
+}
+
+// A 341 dots wide map with a line per scanline. The picture, dimmed, sits at
+// dots 1-256 of the visible lines and every event is a 3 by 3 mark.
+pub fn render(events: &[Event], frame_buffer: &[u8], scanlines: u16) -> Vec<u8> {
+    let height = scanlines as usize;
+    let mut image = Vec::with_capacity(DOTS_PER_LINE * height * 4);
+    for _ in 0..DOTS_PER_LINE * height {
+        image.extend_from_slice(&[
+            BACKGROUND_COLOR.0,
+            BACKGROUND_COLOR.1,
+            BACKGROUND_COLOR.2,
+            0xFF,
+        ]);
+    }
+    for (i, pixel) in frame_buffer.chunks_exact(4).enumerate() {
+        let (x, y) = (i % 256 + 1, i / 256);
+        if y >= height {
+            break;
+        }
+        let index = (y * DOTS_PER_LINE + x) * 4;
+        for channel in 0..3 {
+            image[index + channel] = pixel[channel] / 2;
+        }
+    }
+    for event in events {
+        let color = event.kind.color();
+        for y in event.scanline.saturating_sub(1) as usize..=event.scanline as usize + 1 {
+            for x in event.dot.saturating_sub(1) as usize..=event.dot as usize + 1 {
+                if x < DOTS_PER_LINE && y < height {
+                    let index = (y * DOTS_PER_LINE + x) * 4;
+                    image[index..index + 3].copy_from_slice(&[color.0, color.1, color.2]);
+                }
+            }
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::NES;
+
+    #[test]
+    fn test_render() {
+        let events = [
+            Event {
+                kind: EventKind::Write(0x2005, 0x10),
+                scanline: 100,
+                dot: 200,
+            },
+            Event {
+                kind: EventKind::NMI,
+                scanline: 261,
+                dot: 340,
+            },
+        ];
+        let frame_buffer = vec![0x80; 256 * 240 * 4];
+        let image = render(&events, &frame_buffer, 262);
+        assert_eq!(image.len(), 341 * 262 * 4);
+        let pixel = |x: usize, y: usize| &image[(y * 341 + x) * 4..(y * 341 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), [0x20, 0x20, 0x20, 0xFF]);
+        assert_eq!(pixel(1, 0), [0x40, 0x40, 0x40, 0xFF]);
+        assert_eq!(pixel(257, 239), [0x20, 0x20, 0x20, 0xFF]);
+        assert_eq!(pixel(199, 101), [0x40, 0xFF, 0x40, 0xFF]);
+        assert_eq!(pixel(198, 101), [0x40, 0x40, 0x40, 0xFF]);
+        // marks are cut off at the edges
+        assert_eq!(pixel(339, 260), [0xFF, 0x00, 0xFF, 0xFF]);
+    }
+
+    // $C000: LDA #$90; STA $2000 (NMI on); STA $200D (mirror of $2005);
+    //        JMP $C008
+    // $C100: LDA #$02; STA $4014; RTI (NMI handler)
+    #[test]
+    fn test_logging() {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        let program = [
+            0xA9, 0x90, 0x8D, 0x00, 0x20, 0x8D, 0x0D, 0x20, 0x4C, 0x08, 0xC0,
+        ];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        data[0x110..0x116].copy_from_slice(&[0xA9, 0x02, 0x8D, 0x14, 0x40, 0x40]);
+        data[0x10 + 0x3FFA..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC1, 0x00, 0xC0]);
+        let mut nes = NES::new(&data);
+        assert!(nes.event_log().is_none());
+        nes.set_event_logging(true);
+        nes.frame();
+
+        let log = nes.event_log().unwrap();
+        let kinds = log
+            .last_frame()
+            .iter()
+            .map(|event| event.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                EventKind::Write(0x2000, 0x90),
+                EventKind::Write(0x2005, 0x90),
+                EventKind::NMI,
+                EventKind::Write(0x4014, 0x02),
+            ]
+        );
+        let nmi = log.last_frame()[2];
+        assert_eq!(nmi.scanline, 241);
+        // on the last cycle of the STA that starts at dot 27
+        assert_eq!(log.last_frame()[0].to_string(), "SL:0 DOT:39 $2000=90");
+        drop(log);
+        assert_eq!(nes.render_event_map().unwrap().len(), 341 * 262 * 4);
+
+        nes.set_event_logging(false);
+        assert!(nes.render_event_map().is_none());
+    }
+}
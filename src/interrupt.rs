@@ -1,3 +1,11 @@
+use crate::state::{StateReader, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptKind {
+    NMI,
+    IRQ,
+}
+
 #[derive(Debug)]
 pub struct Interrupt {
     nmi: bool,
@@ -30,6 +38,15 @@ impl Interrupt {
     pub fn clear_irq(&mut self) {
         self.irq = false;
     }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bool(self.nmi);
+        state.write_bool(self.irq);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.nmi = state.read_bool()?;
+        self.irq = state.read_bool()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1,19 +1,32 @@
 extern crate wasm_bindgen;
 
 use wasm_bindgen::prelude::*;
+#[cfg(target_arch = "wasm32")]
 use web_sys::console::log_1;
 
 pub mod apu;
 pub mod cartridge;
+pub mod cdl;
 pub mod controller;
 pub mod cpu;
+pub mod debugger;
 pub mod dma;
+pub mod events;
 pub mod interrupt;
+pub mod memory;
+pub mod movie;
 pub mod nes;
 pub mod ppu;
 pub mod ram;
+pub mod region;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod remote;
 pub mod renderer;
+pub mod rewind;
 pub mod rom;
+pub mod state;
+pub mod symbols;
+pub mod trace;
 
 pub type Byte = u8;
 pub type Word = u16;
@@ -25,27 +38,293 @@ extern "C" {
 }
 
 #[wasm_bindgen]
-pub struct WasmNES(nes::NES);
+pub struct WasmNES(debugger::Debugger);
 
 #[wasm_bindgen]
 impl WasmNES {
     pub fn new(rom_data: &[u8]) -> Self {
-        WasmNES(nes::NES::new(rom_data))
+        WasmNES(debugger::Debugger::new(nes::NES::new(rom_data)))
     }
     pub fn load(&mut self, rom_data: &[u8]) {
-        self.0 = nes::NES::new(rom_data);
+        self.0 = debugger::Debugger::new(nes::NES::new(rom_data));
     }
-    pub fn frame(&mut self) {
-        self.0.frame();
+    pub fn reset(&mut self) {
+        self.0.nes_mut().reset();
+    }
+    pub fn power(&mut self) {
+        self.0.nes_mut().power();
+    }
+    pub fn set_region(&mut self, region: u8) {
+        self.0
+            .nes_mut()
+            .set_region(region::Region::from_byte(region));
+    }
+    // Returns why the emulation stopped, "frame" unless the debugger broke in.
+    pub fn frame(&mut self) -> String {
+        let reason = self.0.run_frame();
+        render_canvas(self.0.nes().frame_buffer());
+        reason.to_string()
     }
     pub fn key_down(&mut self, key: u8) {
-        self.0.key_down(key);
+        self.0.nes_mut().key_down(key);
     }
     pub fn key_up(&mut self, key: u8) {
-        self.0.key_up(key);
+        self.0.nes_mut().key_up(key);
+    }
+
+    // An empty condition always breaks.
+    pub fn add_breakpoint(&mut self, address: u16, condition: &str) -> Result<usize, JsValue> {
+        self.0
+            .add_breakpoint(address, Some(condition).filter(|c| !c.is_empty()))
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    // location is a label or an address expression, e.g. "player_update+3"
+    pub fn add_breakpoint_at(&mut self, location: &str, condition: &str) -> Result<usize, JsValue> {
+        self.0
+            .add_breakpoint_at(location, Some(condition).filter(|c| !c.is_empty()))
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    // space: 0 = CPU, 1 = PPU; kind: 0 = read, 1 = write, 2 = both
+    pub fn add_watchpoint(
+        &mut self,
+        space: u8,
+        start: u16,
+        end: u16,
+        kind: u8,
+        condition: &str,
+    ) -> Result<usize, JsValue> {
+        let space = if space == 1 {
+            debugger::Space::PPU
+        } else {
+            debugger::Space::CPU
+        };
+        let kind = match kind {
+            0 => debugger::WatchKind::Read,
+            1 => debugger::WatchKind::Write,
+            _ => debugger::WatchKind::ReadWrite,
+        };
+        self.0
+            .add_watchpoint(
+                space,
+                start,
+                end,
+                kind,
+                Some(condition).filter(|c| !c.is_empty()),
+            )
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.0.remove(id)
+    }
+    pub fn step_into(&mut self) -> String {
+        self.0.step_into().to_string()
+    }
+    pub fn step_over(&mut self) -> String {
+        self.0.step_over().to_string()
+    }
+    pub fn step_out(&mut self) -> String {
+        self.0.step_out().to_string()
+    }
+    pub fn run_to_scanline(&mut self, scanline: u16) -> String {
+        self.0.run_to_scanline(scanline).to_string()
+    }
+    pub fn run_frame(&mut self) -> String {
+        self.0.run_frame().to_string()
+    }
+
+    // file_name picks the format: .dbg (ca65), .nl (FCEUX) or .mlb (Mesen)
+    pub fn load_symbols(&mut self, file_name: &str, text: &str) -> Result<(), JsValue> {
+        self.0
+            .load_symbols(file_name, text)
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    pub fn disassemble(&self, address: u16) -> String {
+        self.0.disassemble(address)
+    }
+
+    // Keeps the last `capacity` instructions, replacing any running trace.
+    pub fn start_trace(&mut self, capacity: usize) {
+        self.0
+            .set_tracer(Some(trace::Tracer::with_capacity(capacity)));
+    }
+    pub fn stop_trace(&mut self) {
+        self.0.set_tracer(None);
+    }
+    pub fn trace_pc_range(&mut self, start: u16, end: u16) {
+        self.add_trace_filter(trace::TraceFilter::Range(start, end));
+    }
+    pub fn trace_after_breakpoint(&mut self, id: usize) {
+        self.add_trace_filter(trace::TraceFilter::AfterBreakpoint(id));
+    }
+    pub fn trace_nmi_handler(&mut self) {
+        self.add_trace_filter(trace::TraceFilter::NMIHandler);
+    }
+    pub fn trace_dump(&self) -> String {
+        self.0
+            .tracer()
+            .map(|tracer| tracer.dump())
+            .unwrap_or_default()
+    }
+    pub fn set_code_data_logging(&mut self, is_logging: bool) {
+        self.0.nes_mut().set_code_data_logging(is_logging);
+    }
+    // The FCEUX .cdl file, empty when not logging.
+    pub fn code_data_log(&self) -> Vec<u8> {
+        self.0
+            .nes()
+            .code_data_log()
+            .map(|log| log.to_bytes())
+            .unwrap_or_default()
+    }
+    pub fn load_code_data_log(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.0
+            .nes_mut()
+            .load_code_data_log(data)
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    // Follows the call stack and counts cycles per routine.
+    pub fn set_profiling(&mut self, is_profiling: bool) {
+        self.0.nes_mut().cpu_mut().set_call_tracking(is_profiling);
+    }
+    // The last whole frame, or everything since profiling started.
+    pub fn profile_report(&self, is_session: bool) -> String {
+        self.0.profile_report(is_session).unwrap_or_default()
     }
+    pub fn backtrace(&self) -> String {
+        self.0.backtrace().unwrap_or_default()
+    }
+    pub fn save_state(&self) -> Vec<u8> {
+        self.0.nes().save_state()
+    }
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        self.0
+            .nes_mut()
+            .load_state(data)
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    // Starts recording at power-on, or from the current state.
+    pub fn record_movie(&mut self, rom_filename: &str, is_from_save_state: bool) {
+        self.0
+            .nes_mut()
+            .record_movie(rom_filename, is_from_save_state);
+    }
+    // Takes an .fm2 movie.
+    pub fn play_movie(&mut self, text: &str) -> Result<(), JsValue> {
+        movie::Movie::from_fm2(text)
+            .and_then(|movie| self.0.nes_mut().play_movie(movie))
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    pub fn is_playing_movie(&self) -> bool {
+        self.0.nes().is_playing_movie()
+    }
+    // The .fm2 text of the movie recorded or played, if any.
+    pub fn stop_movie(&mut self) -> Option<String> {
+        self.0.nes_mut().stop_movie().map(|movie| movie.to_fm2())
+    }
+    // Frames to run ahead of the one shown, 0 to turn it off. Paused while
+    // breakpoints, watchpoints or a trace are set.
+    pub fn set_run_ahead(&mut self, frames: u32) {
+        self.0.nes_mut().set_run_ahead(frames);
+    }
+    // A snapshot every `interval` frames, 0 to stop, in about `capacity` bytes.
+    pub fn set_rewinding(&mut self, interval: u32, capacity: usize) {
+        self.0.nes_mut().set_rewinding(interval, capacity);
+    }
+    // Steps back a snapshot and shows it, false when there are none left.
+    pub fn rewind(&mut self) -> bool {
+        let is_rewound = self.0.nes_mut().rewind();
+        if is_rewound {
+            render_canvas(self.0.nes().frame_buffer());
+        }
+        is_rewound
+    }
+    // 0 CPU, 1 PPU, 2 OAM, 3 PRG ROM, 4 CHR ROM, 5 PRG RAM
+    pub fn memory_size(&self, space: u8) -> Result<usize, JsValue> {
+        Ok(self.0.nes().memory_size(memory_space(space)?))
+    }
+    // Up to `length` bytes, fewer at the end of the space.
+    pub fn peek(&self, space: u8, address: usize, length: usize) -> Result<Vec<u8>, JsValue> {
+        let space = memory_space(space)?;
+        let nes = self.0.nes();
+        Ok((address..address.saturating_add(length))
+            .map_while(|address| nes.peek(space, address))
+            .collect())
+    }
+    pub fn poke(&mut self, space: u8, address: usize, data: &[u8]) -> Result<(), JsValue> {
+        let space = memory_space(space)?;
+        let nes = self.0.nes_mut();
+        for (i, byte) in data.iter().enumerate() {
+            if !nes.poke(space, address + i, *byte) {
+                return Err(JsValue::from_str("address out of range"));
+            }
+        }
+        Ok(())
+    }
+    // RGBA images of the PPU's memory, see the sizes in `ppu`
+    pub fn name_tables(&self, is_scroll_shown: bool) -> Vec<u8> {
+        self.0.nes().ppu().render_name_tables(is_scroll_shown)
+    }
+    // Palettes 0-3 are for the background, 4-7 for sprites.
+    pub fn pattern_tables(&self, palette_id: u8) -> Vec<u8> {
+        self.0.nes().ppu().render_pattern_tables(palette_id)
+    }
+    pub fn sprite_sheet(&self) -> Vec<u8> {
+        self.0.nes().ppu().render_sprites()
+    }
+    pub fn palette(&self) -> Vec<u8> {
+        self.0.nes().ppu().render_palette()
+    }
+    // One line per sprite with its position, tile, palette and flips.
+    pub fn sprite_list(&self) -> String {
+        self.0
+            .nes()
+            .ppu()
+            .sprite_infos()
+            .iter()
+            .map(|sprite| format!("{}\n", sprite))
+            .collect()
+    }
+    pub fn set_event_logging(&mut self, is_logging: bool) {
+        self.0.nes_mut().set_event_logging(is_logging);
+    }
+    // RGBA, 341 wide with a line per scanline. Empty when not logging.
+    pub fn event_map(&self) -> Vec<u8> {
+        self.0.nes().render_event_map().unwrap_or_default()
+    }
+    // The last frame's events, one per line.
+    pub fn event_list(&self) -> String {
+        self.0
+            .nes()
+            .event_log()
+            .map(|log| {
+                log.last_frame()
+                    .iter()
+                    .map(|event| format!("{}\n", event))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn add_trace_filter(&mut self, filter: trace::TraceFilter) {
+        if let Some(tracer) = self.0.tracer_mut() {
+            tracer.add_filter(filter);
+        }
+    }
+}
+
+fn memory_space(space: u8) -> Result<memory::MemorySpace, JsValue> {
+    memory::MemorySpace::from_byte(space)
+        .ok_or_else(|| JsValue::from_str(&format!("unknown memory space: {}", space)))
 }
 
+#[cfg(target_arch = "wasm32")]
 pub fn log(s: &str) {
     log_1(&JsValue::from(s));
 }
+// Silent natively unless RUST_NES_LOG is set, so tests and benchmarks stay quiet.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn log(s: &str) {
+    static IS_LOGGING: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+    if *IS_LOGGING.get_or_init(|| std::env::var_os("RUST_NES_LOG").is_some()) {
+        eprintln!("{}", s);
+    }
+}
@@ -0,0 +1,26 @@
+// The address spaces a memory viewer can look at.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MemorySpace {
+    CPU,
+    PPU,
+    OAM,
+    // offsets into the PRG and CHR parts of the iNES file
+    ProgramROM,
+    CharacterROM,
+    // $6000-$7FFF on the cartridge
+    ProgramRAM,
+}
+
+impl MemorySpace {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(MemorySpace::CPU),
+            1 => Some(MemorySpace::PPU),
+            2 => Some(MemorySpace::OAM),
+            3 => Some(MemorySpace::ProgramROM),
+            4 => Some(MemorySpace::CharacterROM),
+            5 => Some(MemorySpace::ProgramRAM),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use crate::Byte;
+
+// https://fceux.com/web/help/fm2.html
+// What FCEUX writes into `emuVersion`, the version of the format followed here.
+const EMULATOR_VERSION: u32 = 22020;
+// The buttons of an input line from the left, bit 7 of the keys down to bit 0.
+const BUTTONS: &[u8; 8] = b"RLDUTSBA";
+pub const COMMAND_RESET: Byte = 0x01;
+pub const COMMAND_POWER: Byte = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MovieFrame {
+    // run before the frame
+    pub commands: Byte,
+    // bit n is the key `Controller::key_down(n)` presses
+    pub keys: Byte,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieStart {
+    PowerOn,
+    // this emulator's save state, which FCEUX cannot load
+    SaveState(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieMode {
+    Recording,
+    // the next frame to play
+    Playing(usize),
+}
+
+// The first controller, a frame at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_filename: String,
+    // MD5 of the PRG and CHR ROM, as FCEUX checks it
+    pub rom_checksum: [u8; 16],
+    pub is_pal: bool,
+    pub start: MovieStart,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    pub fn new(
+        rom_filename: &str,
+        rom_checksum: [u8; 16],
+        is_pal: bool,
+        start: MovieStart,
+    ) -> Self {
+        Movie {
+            rom_filename: rom_filename.to_string(),
+            rom_checksum,
+            is_pal,
+            start,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut lines = vec![
+            "version 3".to_string(),
+            format!("emuVersion {}", EMULATOR_VERSION),
+            "rerecordCount 0".to_string(),
+            format!("palFlag {}", self.is_pal as u8),
+            format!("romFilename {}", self.rom_filename),
+            format!("romChecksum base64:{}", encode_base64(&self.rom_checksum)),
+            format!("guid {}", self.guid()),
+            "fourscore 0".to_string(),
+            "microphone 0".to_string(),
+            "port0 1".to_string(),
+            "port1 0".to_string(),
+            "port2 0".to_string(),
+            "FDS 0".to_string(),
+            "NewPPU 0".to_string(),
+        ];
+        if let MovieStart::SaveState(state) = &self.start {
+            lines.push(format!("savestate base64:{}", encode_base64(state)));
+        }
+        for frame in &self.frames {
+            let buttons = BUTTONS
+                .iter()
+                .enumerate()
+                .map(|(i, button)| {
+                    if frame.keys & (0x80 >> i) != 0 {
+                        *button as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect::<String>();
+            lines.push(format!("|{}|{}|||", frame.commands, buttons));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    // Only text movies with a gamepad in the first port.
+    pub fn from_fm2(text: &str) -> Result<Self, String> {
+        let mut movie = Movie::new("", [0; 16], false, MovieStart::PowerOn);
+        let mut has_checksum = false;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if let Some(record) = line.strip_prefix('|') {
+                let frame = parse_frame(record)
+                    .ok_or_else(|| format!("movie line {} is not valid input", number + 1))?;
+                movie.frames.push(frame);
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => {
+                    return Err(format!("movie version {} is not supported", value));
+                }
+                "binary" if value != "0" => {
+                    return Err("binary movies are not supported".to_string());
+                }
+                "palFlag" => movie.is_pal = value == "1",
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "romChecksum" => {
+                    movie.rom_checksum = decode_base64_field(value)?
+                        .try_into()
+                        .map_err(|_| "movie ROM checksum is not an MD5".to_string())?;
+                    has_checksum = true;
+                }
+                "savestate" => movie.start = MovieStart::SaveState(decode_base64_field(value)?),
+                _ => {}
+            }
+        }
+        if !has_checksum {
+            return Err("movie has no ROM checksum".to_string());
+        }
+        Ok(movie)
+    }
+
+    // FCEUX tells movies apart by this, so it is made from the contents.
+    fn guid(&self) -> String {
+        let mut data = self.rom_checksum.to_vec();
+        for frame in &self.frames {
+            data.extend_from_slice(&[frame.commands, frame.keys]);
+        }
+        let hex = md5(&data)
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<String>();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32]
+        )
+    }
+}
+
+// `commands|port0|port1|port2|`, where a button is up as `.` or a space.
+fn parse_frame(record: &str) -> Option<MovieFrame> {
+    let mut fields = record.split('|');
+    let commands = fields.next()?.trim().parse().ok()?;
+    let mut keys = 0;
+    for (i, button) in fields.next().unwrap_or("").bytes().take(8).enumerate() {
+        if button != b'.' && button != b' ' {
+            keys |= 0x80 >> i;
+        }
+    }
+    Some(MovieFrame { commands, keys })
+}
+
+fn decode_base64_field(value: &str) -> Result<Vec<u8>, String> {
+    value
+        .strip_prefix("base64:")
+        .and_then(decode_base64)
+        .ok_or_else(|| format!("movie field is not base64: {}", value))
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - i * 8)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(BASE64[(bits >> (18 - i * 6)) as usize & 0x3F] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE64.iter().position(|&b| b == c)? as u32;
+        bits = bits << 6 | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+    Some(data)
+}
+
+// RFC 1321
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let constants = (0..64)
+        .map(|i| ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32)
+        .collect::<Vec<_>>();
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0x00);
+    }
+    message.extend_from_slice(&((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+    for block in message.chunks_exact(64) {
+        let words = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(constants[i])
+                .wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+        }
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    }
+    let mut digest = [0; 16];
+    for (i, word) in state.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::NES;
+
+    #[test]
+    fn test_md5() {
+        let hex = |data: &[u8]| {
+            md5(data)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        };
+        assert_eq!(hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        // more than one block
+        assert_eq!(
+            hex(&b"1234567890".repeat(8)),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(encode_base64(b"Man"), "TWFu");
+        assert_eq!(encode_base64(b"Ma"), "TWE=");
+        assert_eq!(encode_base64(b"M"), "TQ==");
+        assert_eq!(decode_base64("TWE="), Some(b"Ma".to_vec()));
+        assert_eq!(decode_base64("TQ=="), Some(b"M".to_vec()));
+        assert_eq!(decode_base64("T!"), None);
+        let data = (0..=255).collect::<Vec<u8>>();
+        assert_eq!(decode_base64(&encode_base64(&data)), Some(data));
+    }
+
+    #[test]
+    fn test_fm2() {
+        let text = "version 3\r\nemuVersion 22020\r\nrerecordCount 5\r\npalFlag 1\r\n\
+                    romFilename Some Game\r\nromChecksum base64:AAECAwQFBgcICQoLDA0ODw==\r\n\
+                    comment author someone\r\nport0 1\r\nport1 1\r\n\
+                    |0|R......A|........||\r\n|1|.L.U.S. |...U....||\r\n|2|        |........||\r\n";
+        let movie = Movie::from_fm2(text).unwrap();
+        assert_eq!(movie.rom_filename, "Some Game");
+        assert_eq!(movie.rom_checksum, core::array::from_fn(|i| i as u8));
+        assert!(movie.is_pal);
+        assert_eq!(movie.start, MovieStart::PowerOn);
+        assert_eq!(
+            movie.frames,
+            [
+                MovieFrame {
+                    commands: 0,
+                    keys: 0x81
+                },
+                MovieFrame {
+                    commands: COMMAND_RESET,
+                    keys: 0x54
+                },
+                MovieFrame {
+                    commands: COMMAND_POWER,
+                    keys: 0x00
+                },
+            ]
+        );
+        let written = movie.to_fm2();
+        assert!(written.contains("\n|1|.L.U.S..|||\n"));
+        assert_eq!(Movie::from_fm2(&written), Ok(movie.clone()));
+
+        let mut started = movie.clone();
+        started.start = MovieStart::SaveState(vec![1, 2, 3]);
+        assert!(started.to_fm2().contains("\nsavestate base64:AQID\n"));
+        assert_eq!(Movie::from_fm2(&started.to_fm2()), Ok(started));
+
+        assert_eq!(
+            Movie::from_fm2("version 3\n").err(),
+            Some("movie has no ROM checksum".to_string())
+        );
+        assert_eq!(
+            Movie::from_fm2("version 2\n").err(),
+            Some("movie version 2 is not supported".to_string())
+        );
+        assert_eq!(
+            Movie::from_fm2("binary 1\n").err(),
+            Some("binary movies are not supported".to_string())
+        );
+        assert_eq!(
+            Movie::from_fm2("|x|........|||\n").err(),
+            Some("movie line 1 is not valid input".to_string())
+        );
+    }
+
+    // $C000: NMI and the picture on; JMP $C00A
+    // $C100: INC $00; backdrop = $00 + $10 while A is down; RTI (NMI handler)
+    fn prepare_rom() -> Vec<u8> {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        let program = [
+            0xA9, 0x80, 0x8D, 0x00, 0x20, 0xA9, 0x0A, 0x8D, 0x01, 0x20, 0x4C, 0x0A, 0xC0,
+        ];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        let handler = [
+            0xE6, 0x00, 0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16,
+            0x40, 0x29, 0x01, 0x0A, 0x0A, 0x0A, 0x0A, 0x05, 0x00, 0x29, 0x3F, 0x85, 0x01, 0xA9,
+            0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA5, 0x01, 0x8D, 0x07, 0x20,
+            0x40,
+        ];
+        data[0x110..0x110 + handler.len()].copy_from_slice(&handler);
+        data[0x10 + 0x3FFA..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC1, 0x00, 0xC0]);
+        data
+    }
+
+    fn play(data: &[u8], movie: &Movie, frames: &[Vec<u8>]) -> () {
+        let mut nes = NES::new(data);
+        // whatever came before is undone
+        nes.key_down(7);
+        nes.frame();
+        nes.play_movie(movie.clone()).unwrap();
+        for frame in frames {
+            assert!(nes.is_playing_movie());
+            // the movie holds the keys
+            nes.key_down(0);
+            nes.frame();
+            assert_eq!(nes.frame_buffer(), &frame[..]);
+        }
+        assert!(!nes.is_playing_movie());
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let data = prepare_rom();
+        let mut nes = NES::new(&data);
+        nes.frame();
+        nes.record_movie("test.nes", false);
+        let mut frames = Vec::new();
+        for i in 0..16 {
+            match i {
+                3 => nes.key_down(0),
+                6 => nes.key_up(0),
+                8 => nes.reset(),
+                12 => nes.power(),
+                _ => {}
+            }
+            nes.frame();
+            frames.push(nes.frame_buffer().to_vec());
+        }
+        let movie = nes.stop_movie().unwrap();
+        assert!(nes.stop_movie().is_none());
+        assert_eq!(movie.frames.len(), 16);
+        assert_eq!(movie.frames[3].keys, 0x01);
+        assert_eq!(movie.frames[6].keys, 0x00);
+        assert_eq!(movie.frames[8].commands, COMMAND_RESET);
+        assert_eq!(movie.frames[12].commands, COMMAND_POWER);
+        // the A button shows
+        assert_ne!(frames[3], frames[2]);
+
+        let movie = Movie::from_fm2(&movie.to_fm2()).unwrap();
+        play(&data, &movie, &frames);
+        play(&data, &movie, &frames);
+
+        let mut other = movie.clone();
+        other.rom_checksum[0] ^= 0xFF;
+        assert_eq!(
+            NES::new(&data).play_movie(other).err(),
+            Some("movie is for another ROM".to_string())
+        );
+    }
+
+    #[test]
+    fn test_record_from_save_state() {
+        let data = prepare_rom();
+        let mut nes = NES::new(&data);
+        for _ in 0..5 {
+            nes.frame();
+        }
+        nes.record_movie("test.nes", true);
+        let mut frames = Vec::new();
+        for i in 0..4 {
+            if i == 1 {
+                nes.key_down(0);
+            }
+            nes.frame();
+            frames.push(nes.frame_buffer().to_vec());
+        }
+        let movie = Movie::from_fm2(&nes.stop_movie().unwrap().to_fm2()).unwrap();
+        assert!(matches!(movie.start, MovieStart::SaveState(_)));
+        play(&data, &movie, &frames);
+    }
+}
@@ -1,71 +1,678 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    rc::Rc,
+};
 
 use crate::{
     cartridge::Cartridge,
+    cdl::CodeDataLog,
     controller::Controller,
-    cpu::{CPUBus, CPU},
+    cpu::{Bus, CPUBus, CPU},
+    events::{self, EventLog},
     interrupt,
-    ppu::{PPUBus, PPUImpl, PPU},
+    memory::MemorySpace,
+    movie::{self, Movie, MovieFrame, MovieMode, MovieStart},
+    ppu::{PPUBus, PPUImpl},
+    region::Region,
     renderer::Renderer,
+    rewind::Rewind,
+    state::{self, StateReader, StateWriter},
+    Byte, Word,
 };
 
 pub struct NES {
-    cpu: CPU<PPUImpl>,
+    cpu: CPU<CPUBus<PPUImpl>>,
     ppu: Rc<RefCell<PPUImpl>>,
     controller: Rc<RefCell<Controller>>,
-    dma: Rc<RefCell<crate::dma::DMA<PPUImpl>>>,
+    renderer: Renderer,
+    region: Region,
+    rom_checksum: u32,
+    // MD5 of the PRG and CHR ROM for movies
+    rom_digest: [u8; 16],
+    power_on_state: Vec<u8>,
+    character_rom_size: usize,
+    code_data_log: Option<Rc<RefCell<CodeDataLog>>>,
+    event_log: Option<Rc<RefCell<EventLog>>>,
+    rewind: Option<Rewind>,
+    // frames run ahead of the one shown, see `frame`
+    run_ahead: u32,
+    is_rendering: bool,
+    is_running_ahead: bool,
+    movie: Option<(Movie, MovieMode)>,
+    // reset or power since the last frame started, for the movie
+    commands: Byte,
+    is_frame_start: bool,
 }
 
 impl NES {
     pub fn new(rom_data: &[u8]) -> Self {
         let cartridge = Cartridge::new(rom_data);
+        let rom_digest =
+            movie::md5(&[cartridge.program_rom.data(), cartridge.character_rom.data()].concat());
 
+        let character_rom_size = cartridge.character_rom.size();
         let interrupt = Rc::new(RefCell::new(interrupt::Interrupt::default()));
         let ppu_bus = PPUBus::new(cartridge.character_rom, cartridge.is_horizontal_mirroring);
         let ppu = Rc::new(RefCell::new(PPUImpl::new(ppu_bus, interrupt.clone())));
+        ppu.borrow_mut().set_region(cartridge.region);
         let controller = Rc::new(RefCell::new(Controller::default()));
         let wram = Rc::new(RefCell::new(crate::cpu::WRAM::default()));
-        let dma = Rc::new(RefCell::new(crate::dma::DMA::new(
-            wram.clone(),
-            ppu.clone(),
-        )));
-        let cpu_bus = CPUBus::new(
+        let dma = Rc::new(RefCell::new(crate::dma::DMA::default()));
+        let mut cpu_bus = CPUBus::new(
             cartridge.program_rom,
             wram.clone(),
             ppu.clone(),
             controller.clone(),
             dma.clone(),
         );
+        cpu_bus.set_region(cartridge.region);
         let mut cpu = CPU::new(cpu_bus, interrupt.clone());
         cpu.reset();
 
-        NES {
+        let mut nes = NES {
             cpu,
             ppu,
             controller,
-            dma,
-        }
+            renderer: Renderer::new(),
+            region: cartridge.region,
+            rom_checksum: state::crc32(rom_data),
+            rom_digest,
+            power_on_state: Vec::new(),
+            character_rom_size,
+            code_data_log: None,
+            event_log: None,
+            rewind: None,
+            run_ahead: 0,
+            is_rendering: true,
+            is_running_ahead: false,
+            movie: None,
+            commands: 0,
+            is_frame_start: true,
+        };
+        nes.power_on_state = nes.save_state();
+        nes
+    }
+
+    pub fn reset(&mut self) {
+        self.commands |= movie::COMMAND_RESET;
+        self.cpu.reset();
+    }
+    // Back to the state after `new`, keeping the region and ROM patches.
+    pub fn power(&mut self) {
+        self.commands |= movie::COMMAND_POWER;
+        self.power_on();
+    }
+    fn power_on(&mut self) -> () {
+        let region = self.region;
+        let state = std::mem::take(&mut self.power_on_state);
+        self.restore_state(&state);
+        self.power_on_state = state;
+        self.set_region(region);
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.cpu.bus_mut().set_region(region);
+        self.ppu.borrow_mut().set_region(region);
     }
 
+    // With run-ahead the frame is followed by that many more with the same
+    // input, only the last one drawn, and the machine is put back after the
+    // first. Games that react a few frames late then react on screen at once.
     pub fn frame(&mut self) -> () {
-        loop {
-            let mut cycle = 0;
-            cycle += self.dma.borrow_mut().run();
-            cycle += self.cpu.run();
-            let rendering_data = self.ppu.borrow_mut().run(cycle * 3);
-            if let Some(rendering_data) = rendering_data {
-                let mut renderer = Renderer::new();
-                renderer.render(rendering_data);
-                break;
+        if self.run_ahead == 0 {
+            self.run_frame();
+            return;
+        }
+        // rewinding keeps the picture of every real frame
+        self.is_rendering = self.rewind.is_some();
+        self.run_frame();
+        let state = self.save_state();
+        // the profiler and the logs only see the real frames
+        let call_stack = self.cpu.take_call_stack();
+        self.connect_logs(false);
+        self.is_running_ahead = true;
+        for i in 0..self.run_ahead {
+            self.is_rendering = i + 1 == self.run_ahead;
+            self.run_frame();
+        }
+        self.is_running_ahead = false;
+        self.is_rendering = true;
+        self.restore_state(&state);
+        self.connect_logs(true);
+        self.cpu.set_call_stack(call_stack);
+    }
+    fn connect_logs(&mut self, is_connected: bool) -> () {
+        let event_log = self.event_log.clone().filter(|_| is_connected);
+        let code_data_log = self.code_data_log.clone().filter(|_| is_connected);
+        self.cpu.bus_mut().set_event_log(event_log.clone());
+        self.ppu.borrow_mut().set_event_log(event_log);
+        self.cpu.bus_mut().set_code_data_log(code_data_log.clone());
+        self.ppu.borrow_mut().set_code_data_log(code_data_log);
+    }
+    pub fn run_ahead(&self) -> u32 {
+        self.run_ahead
+    }
+    pub fn set_run_ahead(&mut self, frames: u32) -> () {
+        self.run_ahead = frames;
+    }
+    fn run_frame(&mut self) -> () {
+        while !self.step() {}
+    }
+    // Runs one instruction and returns whether it completed a frame.
+    pub fn step(&mut self) -> bool {
+        if self.is_frame_start && !self.is_running_ahead {
+            self.is_frame_start = false;
+            self.start_movie_frame();
+        }
+        self.cpu.run();
+        if let Some(rendering_data) = self.cpu.bus_mut().take_rendering_data() {
+            // there is no audio to hold back yet
+            if self.is_rendering {
+                self.renderer.render(rendering_data);
+            }
+            let cycle = self.cpu.cycle();
+            if let Some(call_stack) = self.cpu.call_stack_mut() {
+                call_stack.end_frame(cycle);
+            }
+            if let Some(event_log) = self.event_log.as_ref().filter(|_| !self.is_running_ahead) {
+                event_log.borrow_mut().end_frame();
+            }
+            if let Some(mut rewind) = self.rewind.take() {
+                if !self.is_running_ahead && rewind.end_frame() {
+                    rewind.push(self.rewind_snapshot());
+                }
+                self.rewind = Some(rewind);
+            }
+            if !self.is_running_ahead {
+                self.is_frame_start = true;
+            }
+            return true;
+        }
+        false
+    }
+
+    // Starts marking PRG and CHR bytes as code or data, keeping earlier marks.
+    pub fn set_code_data_logging(&mut self, is_logging: bool) -> () {
+        if !is_logging {
+            self.set_code_data_log(None);
+        } else if self.code_data_log.is_none() {
+            let program_rom_size = self.cpu.bus().program_rom_size();
+            let log = CodeDataLog::new(program_rom_size, self.character_rom_size);
+            self.set_code_data_log(Some(log));
+        }
+    }
+    pub fn code_data_log(&self) -> Option<Ref<'_, CodeDataLog>> {
+        self.code_data_log.as_ref().map(|log| log.borrow())
+    }
+    // Continues logging from a .cdl file.
+    pub fn load_code_data_log(&mut self, data: &[u8]) -> Result<(), String> {
+        let program_rom_size = self.cpu.bus().program_rom_size();
+        let log = CodeDataLog::from_bytes(data, program_rom_size, self.character_rom_size)?;
+        self.set_code_data_log(Some(log));
+        Ok(())
+    }
+    fn set_code_data_log(&mut self, log: Option<CodeDataLog>) -> () {
+        self.code_data_log = log.map(|log| Rc::new(RefCell::new(log)));
+        self.cpu
+            .bus_mut()
+            .set_code_data_log(self.code_data_log.clone());
+        self.ppu
+            .borrow_mut()
+            .set_code_data_log(self.code_data_log.clone());
+    }
+
+    // The whole machine at an instruction boundary. The APU is not emulated
+    // yet and NROM has no mapper registers, so neither adds anything.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(self.rom_checksum);
+        state.write_u8(self.region.to_byte());
+        self.cpu.save_state(&mut state);
+        self.cpu.bus().save_state(&mut state);
+        self.ppu.borrow().save_state(&mut state);
+        state.into_bytes()
+    }
+    // Leaves the machine as it was when the state is rejected.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut state = StateReader::new(data, self.rom_checksum)?;
+        let backup = self.save_state();
+        if let Err(error) = self.read_state(&mut state) {
+            let mut state = StateReader::new(&backup, self.rom_checksum)?;
+            self.read_state(&mut state)?;
+            return Err(error);
+        }
+        Ok(())
+    }
+    // For states just saved, skipping the backup.
+    fn restore_state(&mut self, data: &[u8]) -> () {
+        StateReader::new(data, self.rom_checksum)
+            .and_then(|mut state| self.read_state(&mut state))
+            .unwrap();
+    }
+    fn read_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.set_region(Region::from_byte(state::check_below(
+            "region",
+            state.read_u8()?,
+            3,
+        )?));
+        self.cpu.load_state(state)?;
+        self.cpu.bus_mut().load_state(state)?;
+        self.ppu.borrow_mut().load_state(state)?;
+        state.finish()
+    }
+
+    // Keeps a snapshot every `interval` frames in `capacity` bytes of deltas
+    // to step back to. An interval of 0 stops it and drops the snapshots.
+    pub fn set_rewinding(&mut self, interval: u32, capacity: usize) -> () {
+        self.rewind = (interval > 0).then(|| {
+            let mut rewind = Rewind::new(interval, capacity);
+            rewind.push(self.rewind_snapshot());
+            rewind
+        });
+    }
+    pub fn rewind_length(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |rewind| rewind.len())
+    }
+    // Goes back to the previous snapshot along with its picture. When the
+    // machine has moved on since the newest one, that one is gone back to.
+    pub fn rewind(&mut self) -> bool {
+        let Some(mut rewind) = self.rewind.take() else {
+            return false;
+        };
+        // the picture differs from the snapshot while running ahead
+        let state = self.save_state();
+        let frame_buffer_length = self.frame_buffer().len();
+        let latest_state = rewind
+            .latest()
+            .map(|snapshot| &snapshot[..snapshot.len() - frame_buffer_length]);
+        let snapshot = if latest_state == Some(&state[..]) {
+            rewind.pop().map(|snapshot| snapshot.to_vec())
+        } else {
+            rewind.latest().map(|snapshot| snapshot.to_vec())
+        };
+        if let Some(snapshot) = &snapshot {
+            let (state, frame_buffer) =
+                snapshot.split_at(snapshot.len() - self.frame_buffer().len());
+            self.load_state(state).unwrap();
+            self.renderer.restore(frame_buffer);
+        }
+        self.rewind = Some(rewind);
+        snapshot.is_some()
+    }
+    fn rewind_snapshot(&self) -> Vec<u8> {
+        [&self.save_state()[..], self.frame_buffer()].concat()
+    }
+
+    // Records the keys, resets and power cycles of every frame from the next
+    // one, starting at power-on or from the current state.
+    pub fn record_movie(&mut self, rom_filename: &str, is_from_save_state: bool) -> () {
+        let start = if is_from_save_state {
+            MovieStart::SaveState(self.save_state())
+        } else {
+            self.power_on();
+            MovieStart::PowerOn
+        };
+        let is_pal = self.region == Region::PAL;
+        let movie = Movie::new(rom_filename, self.rom_digest, is_pal, start);
+        self.movie = Some((movie, MovieMode::Recording));
+        self.commands = 0;
+        self.is_frame_start = true;
+    }
+    // Takes over the keys until the movie ends.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), String> {
+        if movie.rom_checksum != self.rom_digest {
+            return Err("movie is for another ROM".to_string());
+        }
+        match &movie.start {
+            MovieStart::PowerOn => {
+                if movie.is_pal {
+                    self.set_region(Region::PAL);
+                } else if self.region == Region::PAL {
+                    self.set_region(Region::NTSC);
+                }
+                self.power_on();
             }
+            MovieStart::SaveState(state) => self.load_state(state)?,
         }
+        self.movie = Some((movie, MovieMode::Playing(0)));
+        self.is_frame_start = true;
+        Ok(())
+    }
+    pub fn is_playing_movie(&self) -> bool {
+        match &self.movie {
+            Some((movie, MovieMode::Playing(index))) => *index < movie.frames.len(),
+            _ => false,
+        }
+    }
+    // Hands back the movie being recorded or played.
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie.take().map(|(movie, _)| movie)
+    }
+    fn start_movie_frame(&mut self) -> () {
+        let Some((mut movie, mode)) = self.movie.take() else {
+            return;
+        };
+        let mode = match mode {
+            MovieMode::Recording => {
+                movie.frames.push(MovieFrame {
+                    commands: std::mem::take(&mut self.commands),
+                    keys: self.controller.borrow().keys(),
+                });
+                mode
+            }
+            MovieMode::Playing(index) => {
+                let Some(frame) = movie.frames.get(index).copied() else {
+                    // played to the end
+                    self.movie = Some((movie, mode));
+                    return;
+                };
+                if frame.commands & movie::COMMAND_POWER != 0 {
+                    self.power_on();
+                } else if frame.commands & movie::COMMAND_RESET != 0 {
+                    self.cpu.reset();
+                }
+                self.controller.borrow_mut().set_keys(frame.keys);
+                MovieMode::Playing(index + 1)
+            }
+        };
+        self.movie = Some((movie, mode));
     }
 
+    // Records PPU and mapper register writes, interrupts and sprite 0 hits.
+    pub fn set_event_logging(&mut self, is_logging: bool) -> () {
+        if is_logging == self.event_log.is_some() {
+            return;
+        }
+        self.event_log = is_logging.then(|| Rc::new(RefCell::new(EventLog::new())));
+        self.cpu.bus_mut().set_event_log(self.event_log.clone());
+        self.ppu.borrow_mut().set_event_log(self.event_log.clone());
+    }
+    pub fn event_log(&self) -> Option<Ref<'_, EventLog>> {
+        self.event_log.as_ref().map(|log| log.borrow())
+    }
+    // The events of the last frame over its picture, see `events::render`.
+    pub fn render_event_map(&self) -> Option<Vec<u8>> {
+        let event_log = self.event_log()?;
+        Some(events::render(
+            event_log.last_frame(),
+            self.frame_buffer(),
+            self.region.scanlines(),
+        ))
+    }
+
+    pub fn memory_size(&self, space: MemorySpace) -> usize {
+        match space {
+            MemorySpace::CPU => 0x10000,
+            MemorySpace::PPU => 0x4000,
+            MemorySpace::OAM => 0x100,
+            MemorySpace::ProgramROM => self.cpu.bus().program_rom_size(),
+            MemorySpace::CharacterROM => self.character_rom_size,
+            MemorySpace::ProgramRAM => 0x2000,
+        }
+    }
+    // Reads memory without the side effects a CPU or PPU read has. Registers
+    // read as $FF in CPU space. None past the end of the space.
+    pub fn peek(&self, space: MemorySpace, address: usize) -> Option<Byte> {
+        if address >= self.memory_size(space) {
+            return None;
+        }
+        let data = match space {
+            MemorySpace::CPU => self.cpu.bus().peek(address as Word),
+            MemorySpace::PPU | MemorySpace::CharacterROM => self.ppu().peek(address as Word),
+            MemorySpace::OAM => self.ppu().peek_oam(address as Byte),
+            MemorySpace::ProgramROM => self.cpu.bus().program_rom().read(address as Word),
+            MemorySpace::ProgramRAM => self.cpu.bus().program_ram().read(address as Word),
+        };
+        Some(data)
+    }
+    // Edits memory in place. ROM is patched until the next load, CHR ROM
+    // through its copy in the PPU. Returns false past the end of the space.
+    pub fn poke(&mut self, space: MemorySpace, address: usize, data: Byte) -> bool {
+        if address >= self.memory_size(space) {
+            return false;
+        }
+        match space {
+            MemorySpace::CPU => self.cpu.bus_mut().poke(address as Word, data),
+            MemorySpace::PPU | MemorySpace::CharacterROM => {
+                self.ppu_mut().poke(address as Word, data)
+            }
+            MemorySpace::OAM => self.ppu_mut().poke_oam(address as Byte, data),
+            MemorySpace::ProgramROM => self
+                .cpu
+                .bus_mut()
+                .program_rom_mut()
+                .write(address as Word, data),
+            MemorySpace::ProgramRAM => self
+                .cpu
+                .bus_mut()
+                .program_ram_mut()
+                .write(address as Word, data),
+        }
+        true
+    }
+
+    pub fn cpu(&self) -> &CPU<CPUBus<PPUImpl>> {
+        &self.cpu
+    }
+    pub fn cpu_mut(&mut self) -> &mut CPU<CPUBus<PPUImpl>> {
+        &mut self.cpu
+    }
+    pub fn ppu(&self) -> Ref<'_, PPUImpl> {
+        self.ppu.borrow()
+    }
+    pub fn ppu_mut(&mut self) -> RefMut<'_, PPUImpl> {
+        self.ppu.borrow_mut()
+    }
+
+    pub fn frame_buffer(&self) -> &[u8] {
+        self.renderer.result()
+    }
+
+    // Ignored while a movie plays.
     pub fn key_down(&mut self, key: u8) {
+        if self.is_playing_movie() {
+            return;
+        }
         self.controller.borrow_mut().key_down(key);
     }
 
     pub fn key_up(&mut self, key: u8) {
+        if self.is_playing_movie() {
+            return;
+        }
         self.controller.borrow_mut().key_up(key);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prepare_nes() -> NES {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        data[0x10 + 0x0010] = 0xEA;
+        data[0x10 + 0x4000 + 0x0020] = 0x3C;
+        NES::new(&data)
+    }
+
+    #[test]
+    fn test_peek_poke() {
+        let mut nes = prepare_nes();
+        assert_eq!(nes.memory_size(MemorySpace::ProgramROM), 0x4000);
+        assert_eq!(nes.peek(MemorySpace::ProgramROM, 0x0010), Some(0xEA));
+        assert_eq!(nes.peek(MemorySpace::CPU, 0xC010), Some(0xEA));
+        assert_eq!(nes.peek(MemorySpace::CharacterROM, 0x0020), Some(0x3C));
+        assert_eq!(nes.peek(MemorySpace::ProgramROM, 0x4000), None);
+        assert_eq!(nes.peek(MemorySpace::CPU, 0x10000), None);
+
+        assert!(nes.poke(MemorySpace::ProgramROM, 0x0010, 0x60));
+        assert_eq!(nes.peek(MemorySpace::CPU, 0xC010), Some(0x60));
+        assert!(nes.poke(MemorySpace::CPU, 0x6000, 0x12));
+        assert_eq!(nes.peek(MemorySpace::ProgramRAM, 0x0000), Some(0x12));
+        assert!(nes.poke(MemorySpace::CharacterROM, 0x0020, 0x7E));
+        assert_eq!(nes.peek(MemorySpace::PPU, 0x0020), Some(0x7E));
+        assert!(nes.poke(MemorySpace::OAM, 0x00FF, 0x34));
+        assert_eq!(nes.peek(MemorySpace::OAM, 0x00FF), Some(0x34));
+        assert!(!nes.poke(MemorySpace::OAM, 0x0100, 0x34));
+    }
+
+    #[test]
+    fn test_save_state() {
+        // $C000: INC $00; LDA $2002; JMP $C000 with rendering on
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x55; 0x2000]].concat();
+        let program = [0xE6, 0x00, 0xAD, 0x02, 0x20, 0x4C, 0x00, 0xC0];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        data[0x10 + 0x3FFC..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes = NES::new(&data);
+        nes.cpu_mut().bus_mut().write(0x2001, 0x1E);
+        nes.poke(MemorySpace::PPU, 0x3F01, 0x16);
+        nes.frame();
+        for _ in 0..1000 {
+            nes.step();
+        }
+        let state = nes.save_state();
+
+        nes.frame();
+        nes.frame();
+        let expected_frame = nes.frame_buffer().to_vec();
+        let expected_state = nes.save_state();
+
+        // a fresh machine picks up from the saved state mid-frame
+        let mut other = NES::new(&data);
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        other.frame();
+        other.frame();
+        assert_eq!(other.frame_buffer(), &expected_frame[..]);
+        assert_eq!(other.save_state(), expected_state);
+    }
+
+    // $C000: JMP $C000 with the picture and NMI on
+    // $C100: INC $00; backdrop = $00; RTI (NMI handler)
+    #[test]
+    fn test_run_ahead() {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        data[0x10..0x13].copy_from_slice(&[0x4C, 0x00, 0xC0]);
+        let handler = [
+            0xE6, 0x00, 0xA9, 0x3F, 0x8D, 0x06, 0x20, 0xA9, 0x00, 0x8D, 0x06, 0x20, 0xA5, 0x00,
+            0x29, 0x3F, 0x8D, 0x07, 0x20, 0x40,
+        ];
+        data[0x110..0x110 + handler.len()].copy_from_slice(&handler);
+        data[0x10 + 0x3FFA..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC1, 0x00, 0xC0]);
+        let prepare = || {
+            let mut nes = NES::new(&data);
+            nes.cpu_mut().bus_mut().write(0x2000, 0x80);
+            nes.cpu_mut().bus_mut().write(0x2001, 0x0A);
+            nes
+        };
+        let mut nes = prepare();
+        let mut ahead = prepare();
+        ahead.set_run_ahead(2);
+        nes.set_event_logging(true);
+        ahead.set_event_logging(true);
+        for _ in 0..5 {
+            nes.frame();
+            ahead.frame();
+            // the real machine stays in step
+            assert_eq!(nes.save_state(), ahead.save_state());
+            // and the log holds its frame, not a hidden one
+            let events = nes.event_log().unwrap().last_frame().to_vec();
+            assert_eq!(ahead.event_log().unwrap().last_frame(), &events[..]);
+        }
+        // and shows the picture from two frames later
+        let mut later = prepare();
+        later.load_state(&nes.save_state()).unwrap();
+        later.frame();
+        assert_ne!(later.frame_buffer(), ahead.frame_buffer());
+        later.frame();
+        assert_eq!(later.frame_buffer(), ahead.frame_buffer());
+
+        // rewinding only sees the real frames
+        ahead.set_rewinding(1, 1 << 20);
+        ahead.frame();
+        ahead.frame();
+        assert_eq!(ahead.rewind_length(), 2);
+        assert!(ahead.rewind());
+        assert_eq!(ahead.peek(MemorySpace::CPU, 0), Some(6));
+    }
+
+    #[test]
+    fn test_load_state_errors() {
+        let mut nes = prepare_nes();
+        nes.frame();
+        let state = nes.save_state();
+        nes.frame();
+        let current = nes.save_state();
+
+        assert_eq!(
+            nes.load_state(&state[..state.len() - 1]),
+            Err("save state is truncated".to_string())
+        );
+        assert_eq!(nes.save_state(), current);
+        assert_eq!(
+            nes.load_state(&[state.clone(), vec![0]].concat()),
+            Err("save state has trailing data".to_string())
+        );
+        assert_eq!(nes.save_state(), current);
+
+        let mut data = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00].to_vec();
+        data.resize(0x10 + 0x6000, 0x01);
+        let mut other = NES::new(&data);
+        assert_eq!(
+            other.load_state(&state),
+            Err("save state is for another ROM".to_string())
+        );
+        nes.load_state(&state).unwrap();
+    }
+
+    #[test]
+    fn test_load_state_with_bad_controller_index() {
+        let mut nes = prepare_nes();
+        nes.frame();
+        let state = nes.save_state();
+        nes.cpu_mut().bus_mut().write(0x4016, 0x01);
+        nes.cpu_mut().bus_mut().write(0x4016, 0x00);
+        nes.cpu_mut().bus_mut().read(0x4016);
+        let current = nes.save_state();
+        // the only byte that differs is the controller index
+        let differences = (0..state.len())
+            .filter(|&i| state[i] != current[i])
+            .collect::<Vec<_>>();
+        assert_eq!(differences.len(), 1);
+
+        let mut data = current.clone();
+        data[differences[0]] = 9;
+        assert_eq!(
+            nes.load_state(&data),
+            Err("save state has controller index 9 out of range".to_string())
+        );
+        assert_eq!(nes.save_state(), current);
+        data[differences[0]] = 8;
+        nes.load_state(&data).unwrap();
+        assert!(nes.cpu_mut().bus_mut().read(0x4016) & 0x01 != 0);
+    }
+
+    #[test]
+    fn test_peek_has_no_side_effects() {
+        let mut nes = prepare_nes();
+        // into VBlank
+        while nes.ppu().scanline() != 242 {
+            nes.step();
+        }
+        nes.key_down(0);
+        nes.cpu_mut().bus_mut().write(0x4016, 0x01);
+        nes.cpu_mut().bus_mut().write(0x4016, 0x00);
+        for address in 0x2000..0x4020 {
+            nes.peek(MemorySpace::CPU, address);
+        }
+        let bus = nes.cpu_mut().bus_mut();
+        assert_eq!(bus.read(0x2002) & 0x80, 0x80);
+        assert_eq!(bus.read(0x4016), 0x01);
+    }
+}
@@ -3,21 +3,37 @@ use mockall::automock;
 
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{interrupt::Interrupt, log, ram::RAM, Byte, Cycle, Word};
+use crate::{
+    cdl::{self, CodeDataLog},
+    debugger::{AccessKind, MemoryAccess},
+    events::{EventKind, EventLog},
+    interrupt::Interrupt,
+    log,
+    ram::RAM,
+    region::Region,
+    state::{check_below, StateReader, StateWriter},
+    Byte, Cycle, Word,
+};
 
 mod attribute;
 mod background;
 mod bus;
 mod oam;
+mod open_bus;
 mod palette;
 mod register;
 mod sprite;
 mod tile;
+mod viewer;
 pub use background::{Background, BackgroundCell, BackgroundLine};
 pub use bus::PPUBus;
 pub use palette::Palette;
 pub use sprite::Sprite;
 pub use tile::Tile;
+pub use viewer::{
+    SpriteInfo, NAME_TABLES_HEIGHT, NAME_TABLES_WIDTH, PALETTE_HEIGHT, PALETTE_WIDTH,
+    PATTERN_TABLES_HEIGHT, PATTERN_TABLES_WIDTH, SPRITE_SHEET_HEIGHT, SPRITE_SHEET_WIDTH,
+};
 
 const VRAM_SIZE: usize = 2048;
 pub type VRAM = RAM<VRAM_SIZE>;
@@ -33,83 +49,102 @@ pub struct RenderingData {
 pub const SCREEN_WIDTH: u16 = 256;
 pub const SCREEN_HEIGHT: u16 = 240;
 
+const DOTS_PER_LINE: Cycle = 341;
+
 #[cfg_attr(test, automock)]
 pub trait PPU {
     fn run(&mut self, cycle: Cycle) -> Option<RenderingData>;
     fn read_register(&mut self, addr: Word) -> Byte;
     fn write_register(&mut self, addr: Word, data: Byte) -> ();
     fn transfer_sprite(&mut self, index: Byte, data: Byte) -> ();
+    // The scanline and the dot about to be processed on it.
+    fn position(&self) -> (u16, Cycle);
 }
 
 pub struct PPUImpl {
     bus: PPUBus,
     registers: register::PPURegisters,
     oam: oam::OAM,
+    open_bus: open_bus::OpenBus,
     cycle: Cycle,
     row: u16,
+    is_odd_frame: bool,
+    is_vblank_suppressed: bool,
+    region: Region,
     background: background::Background,
     sprites: Vec<sprite::Sprite>,
     interrupt: Rc<RefCell<Interrupt>>,
+    // PPUDATA accesses since the last take, while the debugger watches memory
+    accesses: Option<Vec<MemoryAccess>>,
+    code_data_log: Option<Rc<RefCell<CodeDataLog>>>,
+    event_log: Option<Rc<RefCell<EventLog>>>,
 }
 
 impl PPU for PPUImpl {
     fn run(&mut self, cycle: Cycle) -> Option<RenderingData> {
-        self.cycle += cycle;
-        if self.cycle >= 341 {
-            self.cycle -= 341;
-
-            if self.row == 0 {
-                self.background.lines.clear();
-                self.build_sprites();
-            }
-
-            if self.has_sprite_hit() {
-                self.registers.set_sprite_zero_hit();
-            }
-
-            if self.row < 240 && self.row % 8 == 0 {
-                self.build_background_line();
-            }
-            if self.row == 240 {
-                self.registers.set_vblank();
-                if self.registers.has_vblank_nmi() {
-                    self.interrupt.borrow_mut().set_nmi();
-                }
+        let mut rendering_data = None;
+        for _ in 0..cycle {
+            if let Some(data) = self.step() {
+                rendering_data = Some(data);
             }
-            if self.row == 261 {
-                self.registers.clear_vblank();
-                self.registers.clear_sprite_zero_hit();
-                self.row = 0;
-                self.interrupt.borrow_mut().clear_nmi();
-
-                return Some(RenderingData {
-                    background: self.background.clone(),
-                    sprites: self.sprites.clone(),
-                });
-            }
-            self.row += 1;
         }
-        None
+        rendering_data
     }
     fn read_register(&mut self, addr: Word) -> Byte {
         match addr {
             0x2002 => {
-                let data = self.registers.read_status();
+                // Reading one dot before VBlank starts reads it as clear and
+                // suppresses it for the whole frame, reading on the same dot
+                // or just after clears it and cancels the NMI.
+                let vblank_start_row = self.region.vblank_start_row();
+                if self.row == vblank_start_row && self.cycle == 1 {
+                    self.is_vblank_suppressed = true;
+                }
+                if self.row == vblank_start_row && (self.cycle == 2 || self.cycle == 3) {
+                    self.interrupt.borrow_mut().clear_nmi();
+                }
+                let status = self.registers.read_status();
                 self.registers.clear_vblank();
                 self.registers.clear_scroll_latch();
                 self.registers.clear_address_latch();
-                data
+                // only the top three bits are driven, the rest come from the latch
+                self.open_bus.refresh(status, 0xE0);
+                self.open_bus.read()
             }
             0x2004 => {
                 // Write OAM data here. Writes will increment OAMADDR after the write
                 // reads during vertical or forced blanking return the value from OAM at that address but do not increment.
-                let oam_address = self.registers.oam_address();
-                self.oam.read(oam_address)
+                // While rendering, secondary OAM is cleared to $FF on dots 1-64
+                // of the visible lines (not the pre-render line) and reads see
+                // that. Sprite evaluation and fetches on the later dots are not
+                // emulated, so reads there still see OAM at OAMADDR.
+                let is_clearing = self.registers.is_rendering_enabled()
+                    && self.row < SCREEN_HEIGHT
+                    && (1..=64).contains(&self.cycle);
+                let data = if is_clearing {
+                    0xFF
+                } else {
+                    let oam_address = self.registers.oam_address();
+                    self.oam.read(oam_address)
+                };
+                self.open_bus.write(data);
+                data
             }
             0x2007 => {
                 let address = self.registers.address();
-                self.bus.read(address)
+                let data = self.bus.read(address);
+                self.log_access(address, data, AccessKind::Read);
+                self.mark_character(address, 1, cdl::READ);
+                if address >= 0x3F00 {
+                    // palette entries are 6 bits wide
+                    self.open_bus.refresh(data, 0x3F);
+                } else {
+                    self.open_bus.write(data);
+                }
+                self.open_bus.read()
             }
+            // write-only registers
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.open_bus.read(),
             _ => {
                 log(&format!("invalid ppu read address: {:04X}", addr));
                 panic!();
@@ -117,8 +152,17 @@ impl PPU for PPUImpl {
         }
     }
     fn write_register(&mut self, addr: Word, data: Byte) -> () {
+        self.open_bus.write(data);
         match addr {
-            0x2000 => self.registers.write_ctrl(data),
+            0x2000 => {
+                let had_vblank_nmi = self.registers.has_vblank_nmi();
+                self.registers.write_ctrl(data);
+                // enabling NMI while the VBlank flag is still set fires it immediately
+                if !had_vblank_nmi && self.registers.has_vblank_nmi() && self.registers.is_vblank()
+                {
+                    self.interrupt.borrow_mut().set_nmi();
+                }
+            }
             0x2001 => self.registers.write_mask(data),
             0x2003 => self.registers.write_oam_address(data),
             0x2004 => {
@@ -130,6 +174,7 @@ impl PPU for PPUImpl {
             0x2006 => self.registers.write_address(data),
             0x2007 => {
                 let address = self.registers.address();
+                self.log_access(address, data, AccessKind::Write);
                 self.bus.write(address, data);
                 self.registers.increment_address();
             }
@@ -144,6 +189,9 @@ impl PPU for PPUImpl {
         self.oam
             .write(((oam_address as u16 + index as u16) % 0x100) as u8, data);
     }
+    fn position(&self) -> (u16, Cycle) {
+        (self.row, self.cycle)
+    }
 }
 
 impl PPUImpl {
@@ -152,17 +200,223 @@ impl PPUImpl {
             bus,
             registers: register::PPURegisters::default(),
             oam: oam::OAM::default(),
+            open_bus: open_bus::OpenBus::default(),
             cycle: 0,
             row: 0,
+            is_odd_frame: false,
+            is_vblank_suppressed: false,
+            region: Region::default(),
             background: background::Background::default(),
             sprites: Vec::new(),
             interrupt,
+            accesses: None,
+            code_data_log: None,
+            event_log: None,
+        }
+    }
+    // A row past the end of the new region's frame, e.g. when going from PAL
+    // to NTSC, moves to the start of its pre-render line so the frame ends.
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        if self.row > region.pre_render_row() {
+            self.row = region.pre_render_row();
+            self.cycle = 0;
+        }
+    }
+    pub fn set_access_logging(&mut self, is_logging: bool) -> () {
+        self.accesses = if is_logging { Some(Vec::new()) } else { None };
+    }
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        self.accesses
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+    pub fn set_code_data_log(&mut self, code_data_log: Option<Rc<RefCell<CodeDataLog>>>) -> () {
+        self.code_data_log = code_data_log;
+    }
+    // The region is left to the owner.
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        self.bus.save_state(state);
+        self.registers.save_state(state);
+        self.oam.save_state(state);
+        self.open_bus.save_state(state);
+        state.write_u32(self.cycle);
+        state.write_u16(self.row);
+        state.write_bool(self.is_odd_frame);
+        state.write_bool(self.is_vblank_suppressed);
+        self.background.save_state(state);
+        state.write_u8(self.sprites.len() as u8);
+        for sprite in &self.sprites {
+            sprite.save_state(state);
+        }
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.bus.load_state(state)?;
+        self.registers.load_state(state)?;
+        self.oam.load_state(state)?;
+        self.open_bus.load_state(state)?;
+        self.cycle = check_below("PPU dot", state.read_u32()?, DOTS_PER_LINE)?;
+        // the region is loaded first
+        self.row = check_below(
+            "PPU row",
+            state.read_u16()?,
+            self.region.pre_render_row() + 1,
+        )?;
+        self.is_odd_frame = state.read_bool()?;
+        self.is_vblank_suppressed = state.read_bool()?;
+        self.background.load_state(state)?;
+        let length = check_below("sprite count", state.read_u8()?, 65)?;
+        self.sprites = (0..length)
+            .map(|_| sprite::Sprite::from_state(state))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+    pub fn set_event_log(&mut self, event_log: Option<Rc<RefCell<EventLog>>>) -> () {
+        self.event_log = event_log;
+    }
+    fn mark_character(&self, address: Word, length: Word, flags: Byte) -> () {
+        if let (Some(code_data_log), 0x0000..=0x1FFF) = (&self.code_data_log, address) {
+            let mut code_data_log = code_data_log.borrow_mut();
+            for offset in address..address + length {
+                code_data_log.mark_character(offset as usize, flags);
+            }
+        }
+    }
+    fn log_access(&mut self, address: Word, data: Byte, kind: AccessKind) -> () {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(MemoryAccess {
+                address,
+                data,
+                kind,
+            });
+        }
+    }
+    pub fn scanline(&self) -> u16 {
+        self.row
+    }
+    // PPU memory without the PPUDATA read buffer or address increment.
+    // $3000-$3EFF mirrors the nametables.
+    pub fn peek(&self, address: Word) -> Byte {
+        self.bus.read(Self::memory_address(address))
+    }
+    pub fn poke(&mut self, address: Word, data: Byte) -> () {
+        self.bus.write(Self::memory_address(address), data);
+    }
+    pub fn peek_oam(&self, address: Byte) -> Byte {
+        self.oam.read(address)
+    }
+    pub fn poke_oam(&mut self, address: Byte, data: Byte) -> () {
+        self.oam.write(address, data);
+    }
+    fn memory_address(address: Word) -> Word {
+        match address % 0x4000 {
+            address @ 0x3000..=0x3EFF => address - 0x1000,
+            address => address,
+        }
+    }
+    pub fn dot(&self) -> Cycle {
+        self.cycle
+    }
+    // Runs a single dot. `cycle` is the dot about to be processed on `row`.
+    fn step(&mut self) -> Option<RenderingData> {
+        if self.cycle == 1 {
+            if self.row == self.region.vblank_start_row() {
+                if !self.is_vblank_suppressed {
+                    self.registers.set_vblank();
+                    if self.registers.has_vblank_nmi() {
+                        self.interrupt.borrow_mut().set_nmi();
+                    }
+                }
+                self.is_vblank_suppressed = false;
+            }
+            if self.row == self.region.pre_render_row() {
+                self.registers.clear_vblank();
+                self.registers.clear_sprite_zero_hit();
+                self.registers.clear_sprite_overflow();
+                if self.registers.is_rendering_enabled() {
+                    self.corrupt_oam();
+                }
+            }
+        }
+        // OAMADDR is reset while sprite tiles are fetched
+        if self.is_rendering_line() && (257..=320).contains(&self.cycle) {
+            self.registers.write_oam_address(0);
+        }
+
+        self.cycle += 1;
+        if self.cycle < self.line_length() {
+            return None;
+        }
+        self.cycle = 0;
+
+        if self.row == 0 {
+            self.background.lines.clear();
+            self.build_sprites();
+        }
+
+        if self.has_sprite_hit() {
+            self.registers.set_sprite_zero_hit();
+            // the hit is checked once the line is done
+            if let Some(event_log) = &self.event_log {
+                let (scanline, dot) = (self.row + 1, 0);
+                event_log
+                    .borrow_mut()
+                    .record(EventKind::SpriteZeroHit, scanline, dot);
+            }
+        }
+
+        if self.row < 240 && self.row.is_multiple_of(8) {
+            self.build_background_line();
+        }
+        if self.row == self.region.pre_render_row() {
+            self.row = 0;
+            self.is_odd_frame = !self.is_odd_frame;
+            self.open_bus.tick_frame();
+
+            // both are rebuilt from scratch on the next frame
+            return Some(RenderingData {
+                background: std::mem::take(&mut self.background),
+                sprites: std::mem::take(&mut self.sprites),
+            });
+        }
+        self.row += 1;
+        None
+    }
+    fn is_rendering_line(&self) -> bool {
+        self.registers.is_rendering_enabled()
+            && (self.row < SCREEN_HEIGHT || self.row == self.region.pre_render_row())
+    }
+    // When rendering starts with OAMADDR at 8 or above, the eight bytes at
+    // OAMADDR & $F8 are copied over the first eight bytes of OAM.
+    fn corrupt_oam(&mut self) -> () {
+        let oam_address = self.registers.oam_address();
+        if oam_address < 8 {
+            return;
+        }
+        let base = oam_address & 0xF8;
+        for i in 0..8 {
+            let data = self.oam.read(base + i);
+            self.oam.write(i, data);
+        }
+    }
+    // The idle dot at the end of the pre-render line is skipped on odd frames
+    // while rendering is enabled.
+    fn line_length(&self) -> Cycle {
+        if self.row == self.region.pre_render_row()
+            && self.region.has_odd_frame_skip()
+            && self.is_odd_frame
+            && self.registers.is_rendering_enabled()
+        {
+            DOTS_PER_LINE - 1
+        } else {
+            DOTS_PER_LINE
         }
     }
     fn build_sprites(&mut self) -> () {
         self.sprites.clear();
         for sprite_data in self.oam.iter() {
-            let sprite = sprite::Sprite::new(&self, sprite_data);
+            let sprite = sprite::Sprite::new(self, sprite_data);
             self.sprites.push(sprite);
         }
     }
@@ -231,11 +485,8 @@ impl PPUImpl {
     }
     fn fetch_tile(&self, tile_id: u8, is_sprite: bool) -> tile::Tile {
         let address = self.pattern_table_address(tile_id, is_sprite);
-        let mut tile_data = [0; 16];
-        for i in 0..16 {
-            tile_data[i as usize] = self.bus.read(address + i as Word);
-        }
-        tile::Tile::new(tile_data)
+        self.mark_character(address, 16, cdl::RENDERED);
+        self.bus.tile(address).clone()
     }
     fn fetch_attribute(&self, tile_x: u16, tile_y: u16) -> attribute::Attribute {
         let address = self.attribute_table_address(tile_x, tile_y);
@@ -252,3 +503,262 @@ impl PPUImpl {
         palette_value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::ROM;
+
+    fn prepare_ppu() -> (PPUImpl, Rc<RefCell<Interrupt>>) {
+        let interrupt = Rc::new(RefCell::new(Interrupt::default()));
+        let ppu_bus = PPUBus::new(ROM::new(vec![]), false);
+        (PPUImpl::new(ppu_bus, interrupt.clone()), interrupt)
+    }
+
+    // advances the ppu so that `dot` on `row` is the next dot to be processed
+    fn run_to(ppu: &mut PPUImpl, row: u16, dot: Cycle) {
+        while ppu.row != row || ppu.cycle != dot {
+            ppu.run(1);
+        }
+    }
+
+    #[test]
+    fn test_vblank_timing() {
+        let (mut ppu, interrupt) = prepare_ppu();
+        ppu.write_register(0x2000, 0x80);
+        run_to(&mut ppu, 241, 1);
+        assert_eq!(ppu.registers.is_vblank(), false);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+        ppu.run(1);
+        assert_eq!(ppu.registers.is_vblank(), true);
+        assert_eq!(interrupt.borrow().is_nmi(), true);
+
+        interrupt.borrow_mut().clear_nmi();
+        run_to(&mut ppu, 261, 1);
+        assert_eq!(ppu.registers.is_vblank(), true);
+        ppu.run(1);
+        assert_eq!(ppu.registers.is_vblank(), false);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+    }
+
+    #[test]
+    fn test_odd_frame_skip() {
+        let (mut ppu, _) = prepare_ppu();
+        let frame_length = |ppu: &mut PPUImpl| {
+            let mut dots = 1;
+            while ppu.run(1).is_none() {
+                dots += 1;
+            }
+            dots
+        };
+        // rendering disabled: every frame is full length
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+
+        ppu.write_register(0x2001, 0x08);
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+        assert_eq!(frame_length(&mut ppu), 341 * 262 - 1);
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+    }
+
+    #[test]
+    fn test_region_frame_length() {
+        let (mut ppu, interrupt) = prepare_ppu();
+        ppu.set_region(Region::PAL);
+        ppu.write_register(0x2000, 0x80);
+        ppu.write_register(0x2001, 0x08);
+        for _ in 0..2 {
+            let mut dots = 1;
+            while ppu.run(1).is_none() {
+                dots += 1;
+            }
+            assert_eq!(dots, 341 * 312);
+        }
+
+        ppu.set_region(Region::Dendy);
+        interrupt.borrow_mut().clear_nmi();
+        run_to(&mut ppu, 291, 1);
+        assert_eq!(ppu.registers.is_vblank(), false);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+        ppu.run(1);
+        assert_eq!(ppu.registers.is_vblank(), true);
+        assert_eq!(interrupt.borrow().is_nmi(), true);
+    }
+
+    #[test]
+    fn test_region_switch_past_frame_end() {
+        let (mut ppu, _) = prepare_ppu();
+        ppu.set_region(Region::PAL);
+        run_to(&mut ppu, 300, 100);
+        ppu.set_region(Region::NTSC);
+        assert_eq!((ppu.row, ppu.cycle), (261, 0));
+        let mut dots = 1;
+        while ppu.run(1).is_none() {
+            dots += 1;
+        }
+        assert_eq!(dots, 341);
+        let mut dots = 1;
+        while ppu.run(1).is_none() {
+            dots += 1;
+        }
+        assert_eq!(dots, 341 * 262);
+    }
+
+    #[test]
+    fn test_oam_address_reset_while_rendering() {
+        let (mut ppu, _) = prepare_ppu();
+        ppu.write_register(0x2003, 0x10);
+        run_to(&mut ppu, 10, 300);
+        assert_eq!(ppu.registers.oam_address(), 0x10);
+
+        ppu.write_register(0x2001, 0x10);
+        ppu.write_register(0x2003, 0x10);
+        ppu.run(1);
+        assert_eq!(ppu.registers.oam_address(), 0x00);
+
+        // untouched outside of the sprite fetch dots
+        run_to(&mut ppu, 11, 0);
+        ppu.write_register(0x2003, 0x10);
+        run_to(&mut ppu, 11, 200);
+        assert_eq!(ppu.registers.oam_address(), 0x10);
+    }
+
+    #[test]
+    fn test_oam_corruption() {
+        let (mut ppu, _) = prepare_ppu();
+        for i in 0..=0xFF {
+            ppu.write_register(0x2004, i as u8);
+        }
+        run_to(&mut ppu, 261, 0);
+        ppu.write_register(0x2001, 0x18);
+        ppu.write_register(0x2003, 0x2D);
+        ppu.run(2);
+        for i in 0..8 {
+            assert_eq!(ppu.oam.read(i), ppu.oam.read(0x28 + i));
+        }
+        assert_eq!(ppu.oam.read(8), 0x08);
+        assert_eq!(ppu.oam.read(0x28), 0x28);
+    }
+
+    #[test]
+    fn test_oam_data_read_while_rendering() {
+        let (mut ppu, _) = prepare_ppu();
+        ppu.write_register(0x2003, 0x00);
+        ppu.write_register(0x2004, 0x12);
+        ppu.write_register(0x2003, 0x00);
+        assert_eq!(ppu.read_register(0x2004), 0x12);
+
+        ppu.write_register(0x2001, 0x08);
+        run_to(&mut ppu, 20, 30);
+        assert_eq!(ppu.read_register(0x2004), 0xFF);
+        run_to(&mut ppu, 20, 64);
+        assert_eq!(ppu.read_register(0x2004), 0xFF);
+        // reads do not increment OAMADDR
+        assert_eq!(ppu.registers.oam_address(), 0x00);
+        // the pre-render line has no secondary OAM to clear
+        run_to(&mut ppu, 261, 30);
+        assert_eq!(ppu.read_register(0x2004), 0x12);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let (mut ppu, _) = prepare_ppu();
+        ppu.write_register(0x2003, 0x5A);
+        assert_eq!(ppu.read_register(0x2000), 0x5A);
+        assert_eq!(ppu.read_register(0x2001), 0x5A);
+        assert_eq!(ppu.read_register(0x2003), 0x5A);
+        assert_eq!(ppu.read_register(0x2005), 0x5A);
+        assert_eq!(ppu.read_register(0x2006), 0x5A);
+
+        // $2002 drives the top three bits only
+        ppu.registers.set_vblank();
+        assert_eq!(ppu.read_register(0x2002), 0x9A);
+        assert_eq!(ppu.read_register(0x2006), 0x9A);
+        assert_eq!(ppu.read_register(0x2002), 0x1A);
+
+        // palette reads leave the top two bits to the latch
+        ppu.write_register(0x2006, 0x3F);
+        ppu.write_register(0x2006, 0x00);
+        ppu.write_register(0x2007, 0x0F);
+        ppu.write_register(0x2006, 0x3F);
+        ppu.write_register(0x2006, 0x00);
+        ppu.write_register(0x2003, 0xC0);
+        assert_eq!(ppu.read_register(0x2007), 0xCF);
+
+        // decays after ~600ms without refresh
+        for _ in 0..36 {
+            ppu.run(341 * 262);
+        }
+        assert_eq!(ppu.read_register(0x2000), 0x00);
+    }
+
+    #[test]
+    fn test_status_read_before_vblank_suppresses_it() {
+        let (mut ppu, interrupt) = prepare_ppu();
+        ppu.write_register(0x2000, 0x80);
+        run_to(&mut ppu, 241, 1);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0);
+        ppu.run(10);
+        assert_eq!(ppu.registers.is_vblank(), false);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+    }
+
+    #[test]
+    fn test_status_read_at_vblank_cancels_nmi() {
+        let (mut ppu, interrupt) = prepare_ppu();
+        ppu.write_register(0x2000, 0x80);
+        run_to(&mut ppu, 241, 2);
+        assert_eq!(interrupt.borrow().is_nmi(), true);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x80);
+        assert_eq!(ppu.registers.is_vblank(), false);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+    }
+
+    #[test]
+    fn test_enabling_nmi_during_vblank() {
+        let (mut ppu, interrupt) = prepare_ppu();
+        run_to(&mut ppu, 245, 0);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+        ppu.write_register(0x2000, 0x80);
+        assert_eq!(interrupt.borrow().is_nmi(), true);
+
+        // rewriting with NMI already enabled does not fire again
+        interrupt.borrow_mut().clear_nmi();
+        ppu.write_register(0x2000, 0x80);
+        assert_eq!(interrupt.borrow().is_nmi(), false);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_event() {
+        let (mut ppu, _) = prepare_ppu();
+        let event_log = Rc::new(RefCell::new(EventLog::new()));
+        ppu.set_event_log(Some(event_log.clone()));
+        ppu.poke_oam(0x00, 10);
+        ppu.write_register(0x2001, 0x18);
+        run_to(&mut ppu, 12, 0);
+        let events = event_log.borrow().events().to_vec();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::SpriteZeroHit);
+        assert_eq!((events[0].scanline, events[0].dot), (11, 0));
+    }
+
+    #[test]
+    fn test_peek_poke() {
+        let (mut ppu, _) = prepare_ppu();
+        ppu.poke(0x2005, 0x11);
+        ppu.poke(0x3F11, 0x22);
+        ppu.poke_oam(0x02, 0xFF);
+        assert_eq!(ppu.peek(0x3005), 0x11);
+        // vertical mirroring
+        assert_eq!(ppu.peek(0x2805), 0x11);
+        assert_eq!(ppu.peek(0x7F31), 0x22);
+        assert_eq!(ppu.peek_oam(0x02), 0xE3);
+
+        // PPUDATA still returns the buffer from before
+        ppu.write_register(0x2006, 0x20);
+        ppu.write_register(0x2006, 0x05);
+        ppu.peek(0x0000);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x11);
+    }
+}
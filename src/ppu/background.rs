@@ -1,3 +1,5 @@
+use crate::state::{check_below, StateReader, StateWriter};
+
 use super::tile::Tile;
 
 #[derive(Debug, Clone)]
@@ -22,3 +24,59 @@ pub struct BackgroundCell {
 }
 pub type BackgroundLine = Vec<BackgroundCell>;
 
+// Only the lines built so far in the running frame are saved.
+impl Background {
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_u8(self.lines.len() as u8);
+        for line in &self.lines {
+            state.write_u8(line.len() as u8);
+            for cell in line {
+                cell.save_state(state);
+            }
+        }
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        let length = check_below("background line count", state.read_u8()?, 31)?;
+        self.lines = (0..length)
+            .map(|_| {
+                // the renderer reads whole lines
+                let length = state.read_u8()?;
+                if length != 32 {
+                    return Err(format!("save state has background line length {}", length));
+                }
+                (0..length)
+                    .map(|_| BackgroundCell::from_state(state))
+                    .collect::<Result<BackgroundLine, String>>()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+}
+
+// Colors index the 64-entry palette when rendering.
+pub fn read_palette_value(state: &mut StateReader) -> Result<[u8; 4], String> {
+    let mut palette_value = [0; 4];
+    for color_id in palette_value.iter_mut() {
+        *color_id = check_below("color", state.read_u8()?, 0x40)?;
+    }
+    Ok(palette_value)
+}
+
+impl BackgroundCell {
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bytes(&self.tile.to_bytes());
+        state.write_bytes(&self.palette_value);
+        state.write_u16(self.scroll_x);
+        state.write_u16(self.scroll_y);
+        state.write_bool(self.is_visible);
+    }
+    pub fn from_state(state: &mut StateReader) -> Result<Self, String> {
+        Ok(BackgroundCell {
+            tile: Tile::new(state.read_array()?),
+            palette_value: read_palette_value(state)?,
+            scroll_x: state.read_u16()?,
+            scroll_y: state.read_u16()?,
+            is_visible: state.read_bool()?,
+        })
+    }
+}
@@ -1,12 +1,21 @@
-use crate::{log, rom::ROM, Byte, Word};
+use crate::{
+    log,
+    rom::ROM,
+    state::{StateReader, StateWriter},
+    Byte, Word,
+};
 
-use super::{palette::Palette, CRAM, VRAM};
+use super::{palette::Palette, tile::Tile, CRAM, VRAM};
+
+const TILE_COUNT: usize = 512;
 
 pub struct PPUBus {
     cram: CRAM,
     vram: VRAM,
     palette: Palette,
     is_horizontal_mirroring: bool,
+    // decoded pattern table tiles, kept in sync with CHR writes
+    tiles: Vec<Tile>,
 }
 
 impl PPUBus {
@@ -15,12 +24,44 @@ impl PPUBus {
         for i in 0..character_rom.size() {
             cram.write(i as Word, character_rom.read(i as Word));
         }
-        PPUBus {
+        let mut bus = PPUBus {
             cram,
             vram: VRAM::default(),
             palette: Palette::default(),
             is_horizontal_mirroring,
+            tiles: Vec::with_capacity(TILE_COUNT),
+        };
+        bus.tiles = (0..TILE_COUNT)
+            .map(|index| bus.decode_tile(index))
+            .collect();
+        bus
+    }
+    // `address` is the first byte of the tile in the pattern tables
+    pub fn tile(&self, address: Word) -> &Tile {
+        &self.tiles[address as usize / 16]
+    }
+    // CHR ROM is saved too as it lives in the same RAM as CHR RAM.
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        self.cram.save_state(state);
+        self.vram.save_state(state);
+        self.palette.save_state(state);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.cram.load_state(state)?;
+        self.vram.load_state(state)?;
+        self.palette.load_state(state)?;
+        self.tiles = (0..TILE_COUNT)
+            .map(|index| self.decode_tile(index))
+            .collect();
+        Ok(())
+    }
+    fn decode_tile(&self, index: usize) -> Tile {
+        let address = (index * 16) as Word;
+        let mut tile_data = [0; 16];
+        for i in 0..16 {
+            tile_data[i] = self.cram.read(address + i as Word);
         }
+        Tile::new(tile_data)
     }
     pub fn read(&self, addr: Word) -> Byte {
         match addr {
@@ -55,7 +96,11 @@ impl PPUBus {
     }
     pub fn write(&mut self, addr: u16, data: u8) -> () {
         match addr {
-            0x0000..=0x1FFF => self.cram.write(addr, data),
+            0x0000..=0x1FFF => {
+                self.cram.write(addr, data);
+                let index = addr as usize / 16;
+                self.tiles[index] = self.decode_tile(index);
+            }
             0x2000..=0x23FF => self.vram.write(addr - 0x2000, data),
             0x2400..=0x27FF => {
                 let addr = addr - 0x2400;
@@ -85,3 +130,24 @@ impl PPUBus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_cache() {
+        let mut character_rom_data = vec![0; 0x2000];
+        character_rom_data[0x0010] = 0b1000_0000;
+        let bus = PPUBus::new(ROM::new(character_rom_data), false);
+        assert_eq!(bus.tile(0x0010).palette_offset(0, 0), 1);
+        assert_eq!(bus.tile(0x0000).palette_offset(0, 0), 0);
+
+        // CHR writes are reflected in the decoded tile
+        let mut bus = bus;
+        bus.write(0x0018, 0b1000_0000);
+        assert_eq!(bus.tile(0x0010).palette_offset(0, 0), 3);
+        bus.write(0x1FFF, 0b0000_0001);
+        assert_eq!(bus.tile(0x1FF0).palette_offset(7, 7), 2);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::state::{StateReader, StateWriter};
+
 #[derive(Debug)]
 pub struct OAM {
     data: [u8; 256],
@@ -10,6 +12,8 @@ impl Default for OAM {
 
 impl OAM {
     pub fn write(&mut self, addr: u8, data: u8) {
+        // bits 2-4 of the attribute byte are not implemented in hardware
+        let data = if addr % 4 == 2 { data & 0xE3 } else { data };
         self.data[addr as usize] = data;
     }
     pub fn read(&self, addr: u8) -> u8 {
@@ -18,12 +22,19 @@ impl OAM {
     pub fn head_y(&self) -> u8 {
         self.data[0]
     }
-    pub fn iter(&self) -> OAMIterator {
+    pub fn iter(&self) -> OAMIterator<'_> {
         OAMIterator {
             oam: self,
             entry_index: 0,
         }
     }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bytes(&self.data);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.data = state.read_array()?;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -48,3 +59,20 @@ impl<'a> Iterator for OAMIterator<'a> {
         Some(data)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write() {
+        let mut oam = OAM::default();
+        for i in 0..4 {
+            oam.write(i, 0xFF);
+        }
+        assert_eq!(oam.read(0), 0xFF);
+        assert_eq!(oam.read(1), 0xFF);
+        assert_eq!(oam.read(2), 0xE3);
+        assert_eq!(oam.read(3), 0xFF);
+    }
+}
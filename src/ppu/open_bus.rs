@@ -0,0 +1,80 @@
+use crate::state::{StateReader, StateWriter};
+
+// The PPU I/O latch. Any write fills it, reads refresh the bits they drive,
+// and bits that are not refreshed decay to 0 after roughly 600ms.
+const DECAY_FRAMES: u8 = 36;
+
+#[derive(Debug, Default)]
+pub struct OpenBus {
+    value: u8,
+    ages: [u8; 8],
+}
+
+impl OpenBus {
+    pub fn read(&self) -> u8 {
+        self.value
+    }
+    pub fn write(&mut self, data: u8) -> () {
+        self.refresh(data, 0xFF);
+    }
+    // only the bits set in `mask` are driven and refreshed
+    pub fn refresh(&mut self, data: u8, mask: u8) -> () {
+        self.value = (self.value & !mask) | (data & mask);
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.ages[bit] = 0;
+            }
+        }
+    }
+    pub fn tick_frame(&mut self) -> () {
+        for bit in 0..8 {
+            if self.ages[bit] < DECAY_FRAMES {
+                self.ages[bit] += 1;
+            }
+            if self.ages[bit] >= DECAY_FRAMES {
+                self.value &= !(1 << bit);
+            }
+        }
+    }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_u8(self.value);
+        state.write_bytes(&self.ages);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.value = state.read_u8()?;
+        self.ages = state.read_array()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh() {
+        let mut open_bus = OpenBus::default();
+        open_bus.write(0xFF);
+        assert_eq!(open_bus.read(), 0xFF);
+        open_bus.refresh(0x00, 0xE0);
+        assert_eq!(open_bus.read(), 0x1F);
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut open_bus = OpenBus::default();
+        open_bus.write(0xFF);
+        for _ in 0..DECAY_FRAMES - 1 {
+            open_bus.tick_frame();
+        }
+        assert_eq!(open_bus.read(), 0xFF);
+        // refreshed bits start decaying again from scratch
+        open_bus.refresh(0xFF, 0xF0);
+        open_bus.tick_frame();
+        assert_eq!(open_bus.read(), 0xF0);
+        for _ in 0..DECAY_FRAMES - 1 {
+            open_bus.tick_frame();
+        }
+        assert_eq!(open_bus.read(), 0x00);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::state::{check_below, StateReader, StateWriter};
+
 #[derive(Debug, Default)]
 pub struct Palette {
     data: [u8; 32],
@@ -8,7 +10,8 @@ impl Palette {
         self.data[Self::real_read_addr(addr) as usize]
     }
     pub fn write(&mut self, addr: u8, data: u8) -> () {
-        self.data[Self::real_write_addr(addr) as usize] = data;
+        // entries are 6 bits wide
+        self.data[Self::real_write_addr(addr) as usize] = data & 0x3F;
     }
     fn real_read_addr(addr: u8) -> u8 {
         if Self::is_background_mirror(addr) {
@@ -32,6 +35,15 @@ impl Palette {
     fn is_sprite_mirror(addr: u8) -> bool {
         addr == 0x10 || addr == 0x14 || addr == 0x18 || addr == 0x1c
     }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bytes(&self.data);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        for color_id in self.data.iter_mut() {
+            *color_id = check_below("color", state.read_u8()?, 0x40)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
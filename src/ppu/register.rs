@@ -1,4 +1,7 @@
-use crate::{Byte, Word};
+use crate::{
+    state::{StateReader, StateWriter},
+    Byte, Word,
+};
 
 use super::SCREEN_WIDTH;
 
@@ -72,7 +75,7 @@ impl PPURegisters {
         self.oam_address
     }
     pub fn increment_oam_address(&mut self) {
-        self.oam_address += 1;
+        self.oam_address = self.oam_address.wrapping_add(1);
     }
     pub fn address(&self) -> Word {
         self.address
@@ -114,15 +117,22 @@ impl PPURegisters {
     pub fn is_sprite_visible(&self) -> bool {
         self.mask & 0b10000 != 0
     }
+    pub fn is_rendering_enabled(&self) -> bool {
+        self.is_background_visible() || self.is_sprite_visible()
+    }
+    // sprite overflow is not emulated yet
+    #[allow(dead_code)]
     pub fn is_sprite_overflow(&self) -> bool {
         self.status & 0b100000 != 0
     }
+    #[allow(dead_code)]
     pub fn set_sprite_overflow(&mut self) {
         self.status |= 0b100000;
     }
     pub fn clear_sprite_overflow(&mut self) {
         self.status &= 0b011111;
     }
+    #[allow(dead_code)]
     pub fn is_sprite_zero_hit(&self) -> bool {
         self.status & 0b1000000 != 0
     }
@@ -154,4 +164,32 @@ impl PPURegisters {
     pub fn real_scroll_y(&self) -> u16 {
         self.scroll_y as u16 + (self.name_table_id() as u16 / 2 * SCREEN_WIDTH)
     }
+
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        for data in [
+            self.ctrl,
+            self.mask,
+            self.status,
+            self.oam_address,
+            self.scroll_x,
+            self.scroll_y,
+        ] {
+            state.write_u8(data);
+        }
+        state.write_u16(self.address);
+        state.write_bool(self.is_first_scroll_write);
+        state.write_bool(self.is_first_address_write);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.ctrl = state.read_u8()?;
+        self.mask = state.read_u8()?;
+        self.status = state.read_u8()?;
+        self.oam_address = state.read_u8()?;
+        self.scroll_x = state.read_u8()?;
+        self.scroll_y = state.read_u8()?;
+        self.address = state.read_u16()?;
+        self.is_first_scroll_write = state.read_bool()?;
+        self.is_first_address_write = state.read_bool()?;
+        Ok(())
+    }
 }
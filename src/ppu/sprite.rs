@@ -1,4 +1,6 @@
-use super::{tile::Tile, PPUImpl};
+use crate::state::{StateReader, StateWriter};
+
+use super::{background::read_palette_value, tile::Tile, PPUImpl};
 
 #[derive(Debug, Clone)]
 pub struct Sprite {
@@ -26,6 +28,28 @@ impl Sprite {
             attribute: SpriteAttribute::new(ppu, oam_entry[2]),
         }
     }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_u8(self.x);
+        state.write_u8(self.y);
+        state.write_bytes(&self.tile.to_bytes());
+        state.write_bytes(&self.attribute.palette_value);
+        state.write_bool(self.attribute.is_low_priority);
+        state.write_bool(self.attribute.is_flip_horizontal);
+        state.write_bool(self.attribute.is_flip_vertical);
+    }
+    pub fn from_state(state: &mut StateReader) -> Result<Self, String> {
+        Ok(Sprite {
+            x: state.read_u8()?,
+            y: state.read_u8()?,
+            tile: Tile::new(state.read_array()?),
+            attribute: SpriteAttribute {
+                palette_value: read_palette_value(state)?,
+                is_low_priority: state.read_bool()?,
+                is_flip_horizontal: state.read_bool()?,
+                is_flip_vertical: state.read_bool()?,
+            },
+        })
+    }
 }
 
 impl SpriteAttribute {
@@ -21,6 +21,18 @@ impl Tile {
     pub fn palette_offset(&self, offset_x: u8, offset_y: u8) -> u8 {
         self.data[offset_y as usize][offset_x as usize]
     }
+    // The 16 pattern table bytes the tile was made from.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut raw_data = [0; 16];
+        for offset_y in 0..8 {
+            for offset_x in 0..8 {
+                let palette_offset = self.data[offset_y][offset_x];
+                raw_data[offset_y] |= (palette_offset & 0b1) << (7 - offset_x);
+                raw_data[offset_y + 8] |= (palette_offset >> 1) << (7 - offset_x);
+            }
+        }
+        raw_data
+    }
 }
 
 #[cfg(test)]
@@ -68,5 +80,6 @@ mod tests {
                 );
             }
         }
+        assert_eq!(tile.to_bytes(), raw_data);
     }
 }
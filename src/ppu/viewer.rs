@@ -0,0 +1,323 @@
+use std::fmt;
+
+use crate::{
+    renderer::{Color, COLORS},
+    Word,
+};
+
+use super::{tile::Tile, PPUImpl};
+
+// Sizes of the RGBA images, in pixels
+pub const NAME_TABLES_WIDTH: usize = 512;
+pub const NAME_TABLES_HEIGHT: usize = 480;
+pub const PATTERN_TABLES_WIDTH: usize = 256;
+pub const PATTERN_TABLES_HEIGHT: usize = 128;
+// 8 by 8 sprites, laid out in OAM order
+pub const SPRITE_SHEET_WIDTH: usize = 64;
+pub const SPRITE_SHEET_HEIGHT: usize = 64;
+// a pixel per entry, background palettes on the first row
+pub const PALETTE_WIDTH: usize = 16;
+pub const PALETTE_HEIGHT: usize = 2;
+
+const SCROLL_WINDOW_COLOR: Color = (0xFF, 0x00, 0xFF);
+
+// An OAM entry decoded for display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpriteInfo {
+    pub index: u8,
+    pub x: u8,
+    pub y: u8,
+    pub tile_id: u8,
+    pub palette_id: u8,
+    pub is_low_priority: bool,
+    pub is_flip_horizontal: bool,
+    pub is_flip_vertical: bool,
+}
+
+// `09 X:40 Y:30 T:02 P:2 HV` with B for behind the background
+impl fmt::Display for SpriteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.is_low_priority, 'B'),
+            (self.is_flip_horizontal, 'H'),
+            (self.is_flip_vertical, 'V'),
+        ]
+        .iter()
+        .filter(|(is_set, _)| *is_set)
+        .map(|(_, flag)| flag)
+        .collect::<String>();
+        write!(
+            f,
+            "{:02} X:{:02X} Y:{:02X} T:{:02X} P:{} {}",
+            self.index, self.x, self.y, self.tile_id, self.palette_id, flags
+        )
+    }
+}
+
+// Images of the PPU's memory for debugging. They read memory the way
+// rendering does, but are not logged as rendered in the CDL.
+impl PPUImpl {
+    // The four nametables with the screen outlined where the scroll puts it,
+    // wrapping around the edges.
+    pub fn render_name_tables(&self, is_scroll_shown: bool) -> Vec<u8> {
+        let mut image = Image::new(NAME_TABLES_WIDTH, NAME_TABLES_HEIGHT);
+        for tile_y in 0..60 {
+            for tile_x in 0..64 {
+                let tile_id = self.fetch_tile_id(tile_x, tile_y);
+                let tile = self.bus.tile(self.pattern_table_address(tile_id, false));
+                let attribute = self.fetch_attribute(tile_x, tile_y);
+                let palette_id = attribute.palette_id(tile_x, tile_y % 30);
+                let palette_value = self.fetch_palette_value(palette_id, false);
+                image.draw_tile(
+                    tile,
+                    &palette_value,
+                    tile_x as usize * 8,
+                    tile_y as usize * 8,
+                );
+            }
+        }
+        if is_scroll_shown {
+            let name_table_id = self.registers.name_table_id() as usize;
+            let left = self.registers.scroll_x() as usize + name_table_id % 2 * 256;
+            let top = self.registers.scroll_y() as usize + name_table_id / 2 * 240;
+            for offset in 0..256 {
+                image.put_color(left + offset, top, SCROLL_WINDOW_COLOR);
+                image.put_color(left + offset, top + 239, SCROLL_WINDOW_COLOR);
+            }
+            for offset in 0..240 {
+                image.put_color(left, top + offset, SCROLL_WINDOW_COLOR);
+                image.put_color(left + 255, top + offset, SCROLL_WINDOW_COLOR);
+            }
+        }
+        image.data
+    }
+
+    // $0000 on the left, $1000 on the right. Palettes 0-3 are the background
+    // ones and 4-7 the sprite ones.
+    pub fn render_pattern_tables(&self, palette_id: u8) -> Vec<u8> {
+        let palette_value = self.fetch_palette_value(palette_id % 4, palette_id % 8 >= 4);
+        let mut image = Image::new(PATTERN_TABLES_WIDTH, PATTERN_TABLES_HEIGHT);
+        for index in 0..512 {
+            let tile = self.bus.tile(index as Word * 16);
+            let x = index / 256 * 128 + index % 16 * 8;
+            let y = index % 256 / 16 * 8;
+            image.draw_tile(tile, &palette_value, x, y);
+        }
+        image.data
+    }
+
+    // The tiles of all 64 sprites as they appear on screen, flipped and in
+    // their palettes. Transparent pixels are left transparent.
+    pub fn render_sprites(&self) -> Vec<u8> {
+        let mut image = Image::new(SPRITE_SHEET_WIDTH, SPRITE_SHEET_HEIGHT);
+        for sprite in self.sprite_infos() {
+            let tile = self
+                .bus
+                .tile(self.pattern_table_address(sprite.tile_id, true));
+            let palette_value = self.fetch_palette_value(sprite.palette_id, true);
+            let left = sprite.index as usize % 8 * 8;
+            let top = sprite.index as usize / 8 * 8;
+            for offset_y in 0..8 {
+                for offset_x in 0..8 {
+                    let tile_x = if sprite.is_flip_horizontal {
+                        7 - offset_x
+                    } else {
+                        offset_x
+                    };
+                    let tile_y = if sprite.is_flip_vertical {
+                        7 - offset_y
+                    } else {
+                        offset_y
+                    };
+                    let palette_offset = tile.palette_offset(tile_x as u8, tile_y as u8);
+                    if palette_offset != 0 {
+                        let color_id = palette_value[palette_offset as usize];
+                        image.put(left + offset_x, top + offset_y, color_id);
+                    }
+                }
+            }
+        }
+        image.data
+    }
+    pub fn sprite_infos(&self) -> Vec<SpriteInfo> {
+        (0..64)
+            .map(|index| {
+                let entry = |offset: u8| self.oam.read(index * 4 + offset);
+                let attribute = entry(2);
+                SpriteInfo {
+                    index,
+                    x: entry(3),
+                    y: entry(0),
+                    tile_id: entry(1),
+                    palette_id: attribute & 0b11,
+                    is_low_priority: attribute & 0b10_0000 != 0,
+                    is_flip_horizontal: attribute & 0b100_0000 != 0,
+                    is_flip_vertical: attribute & 0b1000_0000 != 0,
+                }
+            })
+            .collect()
+    }
+
+    // The 32 palette entries, mirrors read like the PPU reads them.
+    pub fn render_palette(&self) -> Vec<u8> {
+        let mut image = Image::new(PALETTE_WIDTH, PALETTE_HEIGHT);
+        for palette_id in 0..8 {
+            let palette_value = self.fetch_palette_value(palette_id % 4, palette_id >= 4);
+            for (i, color_id) in palette_value.iter().enumerate() {
+                let index = palette_id as usize * 4 + i;
+                image.put(index % 16, index / 16, *color_id);
+            }
+        }
+        image.data
+    }
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    // starts out transparent
+    fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            data: vec![0; width * height * 4],
+        }
+    }
+    fn draw_tile(&mut self, tile: &Tile, palette_value: &[u8; 4], left: usize, top: usize) -> () {
+        for offset_y in 0..8 {
+            for offset_x in 0..8 {
+                let palette_offset = tile.palette_offset(offset_x as u8, offset_y as u8);
+                let color_id = palette_value[palette_offset as usize];
+                self.put(left + offset_x, top + offset_y, color_id);
+            }
+        }
+    }
+    fn put(&mut self, x: usize, y: usize, color_id: u8) -> () {
+        self.put_color(x, y, COLORS[color_id as usize % COLORS.len()]);
+    }
+    // Wraps around the edges.
+    fn put_color(&mut self, x: usize, y: usize, color: Color) -> () {
+        let index = ((y % self.height) * self.width + x % self.width) * 4;
+        self.data[index..index + 4].copy_from_slice(&[color.0, color.1, color.2, 0xFF]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interrupt::Interrupt, ppu::PPUBus, ppu::PPU, rom::ROM};
+    use std::{cell::RefCell, rc::Rc};
+
+    fn prepare_ppu() -> PPUImpl {
+        // tile 1 is solid color 1, tile 2 has color 3 in its top left pixel
+        let mut character_rom = vec![0; 0x2000];
+        character_rom[0x10..0x18].fill(0xFF);
+        character_rom[0x20] = 0x80;
+        character_rom[0x28] = 0x80;
+        let bus = PPUBus::new(ROM::new(character_rom), false);
+        let mut ppu = PPUImpl::new(bus, Rc::new(RefCell::new(Interrupt::default())));
+        // background palette 1 and sprite palette 2
+        for (address, color_id) in [(0x3F00, 0x0F), (0x3F05, 0x16), (0x3F1B, 0x2A)] {
+            ppu.poke(address, color_id);
+        }
+        ppu
+    }
+
+    fn pixel(image: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let index = (y * width + x) * 4;
+        image[index..index + 4].try_into().unwrap()
+    }
+    fn color(color_id: u8) -> [u8; 4] {
+        let color = COLORS[color_id as usize];
+        [color.0, color.1, color.2, 0xFF]
+    }
+
+    #[test]
+    fn test_name_tables() {
+        let mut ppu = prepare_ppu();
+        // tile 1 at the top left of $2400 in palette 1, shown right of $2000
+        ppu.poke(0x2400, 0x01);
+        ppu.poke(0x27C0, 0x01);
+        let image = ppu.render_name_tables(false);
+        assert_eq!(image.len(), NAME_TABLES_WIDTH * NAME_TABLES_HEIGHT * 4);
+        assert_eq!(pixel(&image, 512, 256, 0), color(0x16));
+        assert_eq!(pixel(&image, 512, 263, 7), color(0x16));
+        assert_eq!(pixel(&image, 512, 264, 0), color(0x0F));
+        // vertical mirroring puts $2400 under itself at $2C00
+        assert_eq!(pixel(&image, 512, 256, 240), color(0x16));
+
+        // the window starts at X 16 on $2400 and wraps at the right edge
+        ppu.write_register(0x2000, 0x01);
+        ppu.write_register(0x2005, 0x10);
+        ppu.write_register(0x2005, 0x00);
+        let image = ppu.render_name_tables(true);
+        assert_eq!(pixel(&image, 512, 272, 100), color_of(SCROLL_WINDOW_COLOR));
+        assert_eq!(pixel(&image, 512, 15, 100), color_of(SCROLL_WINDOW_COLOR));
+        assert_eq!(pixel(&image, 512, 500, 239), color_of(SCROLL_WINDOW_COLOR));
+        assert_eq!(pixel(&image, 512, 100, 100), color(0x0F));
+    }
+    fn color_of(color: Color) -> [u8; 4] {
+        [color.0, color.1, color.2, 0xFF]
+    }
+
+    #[test]
+    fn test_pattern_tables() {
+        let ppu = prepare_ppu();
+        let image = ppu.render_pattern_tables(1);
+        assert_eq!(
+            image.len(),
+            PATTERN_TABLES_WIDTH * PATTERN_TABLES_HEIGHT * 4
+        );
+        assert_eq!(pixel(&image, 256, 8, 0), color(0x16));
+        assert_eq!(pixel(&image, 256, 15, 7), color(0x16));
+        assert_eq!(pixel(&image, 256, 0, 0), color(0x0F));
+        // the $1000 table holds nothing
+        assert_eq!(pixel(&image, 256, 136, 0), color(0x0F));
+
+        let image = ppu.render_pattern_tables(6);
+        assert_eq!(pixel(&image, 256, 16, 0), color(0x2A));
+    }
+
+    #[test]
+    fn test_sprites() {
+        let mut ppu = prepare_ppu();
+        // sprite 9 is tile 2 in palette 2, flipped both ways
+        for (offset, data) in [0x30, 0x02, 0xC2, 0x40].into_iter().enumerate() {
+            ppu.poke_oam(9 * 4 + offset as u8, data);
+        }
+        let sprites = ppu.sprite_infos();
+        assert_eq!(sprites.len(), 64);
+        assert_eq!(
+            sprites[9],
+            SpriteInfo {
+                index: 9,
+                x: 0x40,
+                y: 0x30,
+                tile_id: 0x02,
+                palette_id: 2,
+                is_low_priority: false,
+                is_flip_horizontal: true,
+                is_flip_vertical: true,
+            }
+        );
+        assert_eq!(sprites[9].to_string(), "09 X:40 Y:30 T:02 P:2 HV");
+        let image = ppu.render_sprites();
+        assert_eq!(pixel(&image, 64, 15, 15), color(0x2A));
+        assert_eq!(pixel(&image, 64, 8, 8), [0; 4]);
+    }
+
+    #[test]
+    fn test_palette() {
+        let ppu = prepare_ppu();
+        let image = ppu.render_palette();
+        assert_eq!(image.len(), PALETTE_WIDTH * PALETTE_HEIGHT * 4);
+        assert_eq!(pixel(&image, 16, 5, 0), color(0x16));
+        assert_eq!(pixel(&image, 16, 11, 1), color(0x2A));
+        // $3F10 mirrors the backdrop
+        assert_eq!(pixel(&image, 16, 0, 1), color(0x0F));
+    }
+}
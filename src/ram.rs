@@ -1,9 +1,11 @@
-use crate::log;
+use crate::state::{StateReader, StateWriter};
 
 pub struct RAM<const N: usize> {
     data: Box<[u8; N]>,
 }
 
+// Real RAM comes up with whatever it held, but movies need every power-on to
+// start the same.
 impl<const N: usize> Default for RAM<N> {
     fn default() -> Self {
         RAM {
@@ -28,6 +30,13 @@ impl<const N: usize> RAM<N> {
     pub fn reset(&mut self) -> () {
         self.data.fill(0);
     }
+    pub fn save_state(&self, state: &mut StateWriter) -> () {
+        state.write_bytes(&self.data[..]);
+    }
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.data.copy_from_slice(state.read_bytes(N)?);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,78 @@
+use crate::Cycle;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Region {
+    #[default]
+    NTSC,
+    PAL,
+    Dendy,
+}
+
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+impl Region {
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            1 => Region::PAL,
+            2 => Region::Dendy,
+            _ => Region::NTSC,
+        }
+    }
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            Region::NTSC => 0,
+            Region::PAL => 1,
+            Region::Dendy => 2,
+        }
+    }
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL | Region::Dendy => 312,
+        }
+    }
+    pub fn vblank_start_row(&self) -> u16 {
+        match self {
+            Region::NTSC | Region::PAL => 241,
+            // Dendy keeps NTSC-like VBlank length and pads the post-render lines instead
+            Region::Dendy => 291,
+        }
+    }
+    pub fn pre_render_row(&self) -> u16 {
+        self.scanlines() - 1
+    }
+    pub fn has_odd_frame_skip(&self) -> bool {
+        *self == Region::NTSC
+    }
+    // PPU dots per CPU cycle as numerator / denominator
+    pub fn clock_ratio(&self) -> (Cycle, Cycle) {
+        match self {
+            Region::NTSC | Region::Dendy => (3, 1),
+            Region::PAL => (16, 5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing() {
+        assert_eq!(Region::NTSC.pre_render_row(), 261);
+        assert_eq!(Region::PAL.pre_render_row(), 311);
+        assert_eq!(Region::Dendy.pre_render_row(), 311);
+        // vblank lasts 20 lines on NTSC and Dendy, 70 lines on PAL
+        assert_eq!(
+            Region::NTSC.pre_render_row() - Region::NTSC.vblank_start_row(),
+            20
+        );
+        assert_eq!(
+            Region::PAL.pre_render_row() - Region::PAL.vblank_start_row(),
+            70
+        );
+        assert_eq!(
+            Region::Dendy.pre_render_row() - Region::Dendy.vblank_start_row(),
+            20
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+};
+
+use crate::{
+    cpu::Bus,
+    debugger::{Debugger, StopReason},
+    memory::MemorySpace,
+    Word,
+};
+
+const MAX_READ_LENGTH: i64 = 0x10000;
+const MAX_DISASSEMBLE_COUNT: i64 = 1000;
+
+// A line based debugging protocol on a localhost TCP socket.
+//
+// Every command gets one reply line, `ok ...` or `error <message>`, except
+// `disassemble` whose `ok <count>` is followed by that many lines. Lines
+// starting with `event` are pushed whenever the emulation stops or, while
+// running, finishes a frame:
+//
+//   pause | continue | step | next | finish | detach
+//   break <location> [condition]     ok <id>
+//   delete <id>
+//   registers                        ok A=00 X=00 Y=00 P=24 S=FD PC=C000 CYC=7
+//   set <a|x|y|p|s|pc> <value>
+//   print <expression>               ok <value>
+//   read <address> <length>          ok 01 02 ...
+//   write <address> <byte>...        registers are skipped
+//   disassemble <address> [count]
+//
+// Addresses, values and conditions are debugger expressions, so labels work.
+// `read` takes at most $10000 bytes and `disassemble` at most 1000 lines.
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    // Port 0 picks a free port, see `local_addr`.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        Ok(Server { listener })
+    }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    // Waits for a client and serves it until it detaches or disconnects. The
+    // emulation starts paused.
+    pub fn serve(&self, debugger: &mut Debugger) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        let mut session = Session {
+            debugger,
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            line: Vec::new(),
+            is_running: false,
+        };
+        session.run()
+    }
+}
+
+struct Session<'a> {
+    debugger: &'a mut Debugger,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    // a command read up to here, while running reads do not block
+    line: Vec<u8>,
+    is_running: bool,
+}
+
+enum Input {
+    Command(String),
+    // nothing complete came in while running
+    Nothing,
+    Closed,
+}
+
+enum Reply {
+    Line(String),
+    Lines(Vec<String>),
+    // the command stopped the emulation, `ok` is followed by an event
+    Stopped(String),
+    Detach,
+}
+
+impl Session<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            if self.is_running {
+                let reason = self.debugger.run_frame();
+                if reason == StopReason::Frame {
+                    self.send("event frame")?;
+                } else {
+                    self.is_running = false;
+                    self.send_stopped(&reason.to_string())?;
+                }
+            }
+            self.reader.get_ref().set_nonblocking(self.is_running)?;
+            let command = match self.read_command()? {
+                Input::Command(command) => command,
+                Input::Nothing => continue,
+                Input::Closed => return Ok(()),
+            };
+            match self.execute(&command) {
+                Ok(Reply::Line(text)) => self.send(&format!("ok{}", prefixed(&text)))?,
+                Ok(Reply::Lines(lines)) => {
+                    self.send(&format!("ok {}", lines.len()))?;
+                    for line in lines {
+                        self.send(&line)?;
+                    }
+                }
+                Ok(Reply::Stopped(reason)) => {
+                    self.send("ok")?;
+                    self.send_stopped(&reason)?;
+                }
+                Ok(Reply::Detach) => {
+                    self.send("ok")?;
+                    return Ok(());
+                }
+                Err(error) => self.send(&format!("error {}", error))?,
+            }
+        }
+    }
+
+    fn read_command(&mut self) -> io::Result<Input> {
+        match self.reader.read_until(b'\n', &mut self.line) {
+            Ok(_) if self.line.ends_with(b"\n") => {
+                let command = String::from_utf8_lossy(&self.line).trim().to_string();
+                self.line.clear();
+                Ok(Input::Command(command))
+            }
+            // the end of the stream, maybe after a partial line
+            Ok(_) => Ok(Input::Closed),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(Input::Nothing),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn execute(&mut self, command: &str) -> Result<Reply, String> {
+        let (name, arguments) = command.split_once(' ').unwrap_or((command, ""));
+        let arguments = arguments.trim();
+        let words = arguments.split_whitespace().collect::<Vec<_>>();
+        match name {
+            "pause" => {
+                self.is_running = false;
+                Ok(Reply::Stopped("pause".to_string()))
+            }
+            "continue" => {
+                self.is_running = true;
+                Ok(Reply::Line(String::new()))
+            }
+            "step" => Ok(Reply::Stopped(self.debugger.step_into().to_string())),
+            "next" => Ok(Reply::Stopped(self.debugger.step_over().to_string())),
+            "finish" => Ok(Reply::Stopped(self.debugger.step_out().to_string())),
+            "detach" => Ok(Reply::Detach),
+            "break" => {
+                let (location, condition) = arguments.split_once(' ').unwrap_or((arguments, ""));
+                let condition = Some(condition.trim()).filter(|c| !c.is_empty());
+                let id = self.debugger.add_breakpoint_at(location, condition)?;
+                Ok(Reply::Line(id.to_string()))
+            }
+            "delete" => {
+                let id = words.first().and_then(|id| id.parse().ok());
+                match id.map(|id| self.debugger.remove(id)) {
+                    Some(true) => Ok(Reply::Line(String::new())),
+                    _ => Err(format!("no breakpoint {}", arguments)),
+                }
+            }
+            "registers" => Ok(Reply::Line(self.registers())),
+            "set" => {
+                let [register, value] = words[..] else {
+                    return Err("usage: set <register> <value>".to_string());
+                };
+                let value = self.debugger.evaluate(value)?;
+                let register_file = self.debugger.nes_mut().cpu_mut().get_register();
+                match register.to_ascii_lowercase().as_str() {
+                    "a" => register_file.set_a(value as u8),
+                    "x" => register_file.set_x(value as u8),
+                    "y" => register_file.set_y(value as u8),
+                    "p" => register_file.set_p(value as u8),
+                    "s" | "sp" => register_file.set_s(value as u8),
+                    "pc" => register_file.set_pc(value as Word),
+                    _ => return Err(format!("unknown register: {}", register)),
+                }
+                Ok(Reply::Line(String::new()))
+            }
+            "print" => Ok(Reply::Line(self.debugger.evaluate(arguments)?.to_string())),
+            "read" => {
+                let [address, length] = words[..] else {
+                    return Err("usage: read <address> <length>".to_string());
+                };
+                let address = self.debugger.evaluate(address)? as Word;
+                let length =
+                    check_count("length", self.debugger.evaluate(length)?, MAX_READ_LENGTH)?;
+                let bus = self.debugger.nes().cpu().bus();
+                let bytes = (0..length)
+                    .map(|i| format!("{:02X}", bus.peek(address.wrapping_add(i as Word))))
+                    .collect::<Vec<_>>();
+                Ok(Reply::Line(bytes.join(" ")))
+            }
+            "write" => {
+                let Some((address, bytes)) = words.split_first() else {
+                    return Err("usage: write <address> <byte>...".to_string());
+                };
+                let address = self.debugger.evaluate(address)? as Word;
+                let bytes = bytes
+                    .iter()
+                    .map(|byte| self.debugger.evaluate(byte))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let nes = self.debugger.nes_mut();
+                for (i, byte) in bytes.into_iter().enumerate() {
+                    let address = address.wrapping_add(i as Word) as usize;
+                    nes.poke(MemorySpace::CPU, address, byte as u8);
+                }
+                Ok(Reply::Line(String::new()))
+            }
+            "disassemble" => {
+                let (address, count) = match words[..] {
+                    [address] => (address, "1"),
+                    [address, count] => (address, count),
+                    _ => return Err("usage: disassemble <address> [count]".to_string()),
+                };
+                let mut address = self.debugger.evaluate(address)? as Word;
+                let count = check_count(
+                    "count",
+                    self.debugger.evaluate(count)?,
+                    MAX_DISASSEMBLE_COUNT,
+                )?;
+                let mut lines = Vec::new();
+                for _ in 0..count {
+                    let length = self.debugger.nes().cpu().disassemble(address).length();
+                    lines.push(format!(
+                        "{:04X} {}",
+                        address,
+                        self.debugger.disassemble(address)
+                    ));
+                    address = address.wrapping_add(length as Word);
+                }
+                Ok(Reply::Lines(lines))
+            }
+            _ => Err(format!("unknown command: {}", name)),
+        }
+    }
+
+    fn registers(&self) -> String {
+        let cpu = self.debugger.nes().cpu();
+        let register = cpu.register();
+        format!(
+            "A={:02X} X={:02X} Y={:02X} P={:02X} S={:02X} PC={:04X} CYC={}",
+            register.get_a(),
+            register.get_x(),
+            register.get_y(),
+            register.get_p(),
+            register.get_s(),
+            register.get_pc(),
+            cpu.cycle()
+        )
+    }
+
+    fn send_stopped(&mut self, reason: &str) -> io::Result<()> {
+        let pc = self.debugger.nes().cpu().register().get_pc();
+        self.send(&format!("event stopped {} pc={:04X}", reason, pc))
+    }
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        // replies go out whole even while reads do not block
+        self.writer.set_nonblocking(false)?;
+        writeln!(self.writer, "{}", line)
+    }
+}
+
+// Client supplied sizes, kept small enough to answer right away.
+fn check_count(name: &str, value: i64, max: i64) -> Result<i64, String> {
+    if !(0..=max).contains(&value) {
+        return Err(format!("{} {} out of range, at most {}", name, value, max));
+    }
+    Ok(value)
+}
+
+fn prefixed(text: &str) -> String {
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!(" {}", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nes::NES;
+    use std::thread;
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+    impl Client {
+        fn line(&mut self) -> String {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        }
+        fn command(&mut self, command: &str) -> String {
+            writeln!(self.writer, "{}", command).unwrap();
+            self.line()
+        }
+        // skips frame events up to the line
+        fn wait_for(&mut self, expected: &str) -> () {
+            loop {
+                let line = self.line();
+                if line == expected {
+                    return;
+                }
+                assert_eq!(line, "event frame");
+            }
+        }
+    }
+
+    // $C000: LDX #$00; INX; STX $0300; JMP $C002
+    fn prepare_debugger() -> Debugger {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        let program = [0xA2, 0x00, 0xE8, 0x8E, 0x00, 0x03, 0x4C, 0x02, 0xC0];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        data[0x10 + 0x3FFC..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        Debugger::new(NES::new(&data))
+    }
+
+    #[test]
+    fn test_session() {
+        let mut debugger = prepare_debugger();
+        let server = Server::bind(0).unwrap();
+        let address = server.local_addr().unwrap();
+        assert!(address.ip().is_loopback());
+
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut client = Client {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            };
+            assert_eq!(
+                client.command("registers"),
+                "ok A=00 X=00 Y=00 P=24 S=FD PC=C000 CYC=7"
+            );
+            assert_eq!(client.command("step"), "ok");
+            assert_eq!(client.line(), "event stopped step pc=C002");
+
+            assert_eq!(client.command("write $0300 1 2"), "ok");
+            assert_eq!(client.command("read $0300 2"), "ok 01 02");
+            assert_eq!(client.command("print [$0300] + 1"), "ok 2");
+            assert_eq!(client.command("set x $10"), "ok");
+            assert_eq!(client.command("print x"), "ok 16");
+            assert_eq!(client.command("disassemble $C000 2"), "ok 2");
+            assert_eq!(client.line(), "C000 LDX #$00");
+            assert_eq!(client.line(), "C002 INX");
+
+            assert_eq!(client.command("break $C003 X == $12"), "ok 1");
+            assert_eq!(client.command("continue"), "ok");
+            client.wait_for("event stopped breakpoint 1 pc=C003");
+            assert_eq!(client.command("print x"), "ok 18");
+            assert_eq!(client.command("delete 1"), "ok");
+            assert_eq!(client.command("delete 1"), "error no breakpoint 1");
+
+            // frames are pushed while running
+            assert_eq!(client.command("continue"), "ok");
+            assert_eq!(client.line(), "event frame");
+            writeln!(client.writer, "pause").unwrap();
+            client.wait_for("ok");
+            assert!(client.line().starts_with("event stopped pause pc="));
+
+            assert_eq!(client.command("bogus"), "error unknown command: bogus");
+            assert_eq!(client.command("set q 1"), "error unknown register: q");
+            assert_eq!(
+                client.command("read 0 1000000000000"),
+                "error length 1000000000000 out of range, at most 65536"
+            );
+            assert_eq!(
+                client.command("disassemble $C000 99999999999"),
+                "error count 99999999999 out of range, at most 1000"
+            );
+            assert_eq!(
+                client.command("read 0 -1"),
+                "error length -1 out of range, at most 65536"
+            );
+            assert_eq!(client.command("detach"), "ok");
+        });
+        server.serve(&mut debugger).unwrap();
+        client.join().unwrap();
+        assert_eq!(debugger.breakpoints().len(), 0);
+    }
+}
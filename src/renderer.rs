@@ -1,21 +1,25 @@
-use crate::{
-    ppu::{Background, BackgroundCell, RenderingData, Sprite, SCREEN_HEIGHT, SCREEN_WIDTH},
-    render_canvas,
-};
+use crate::ppu::{Background, BackgroundCell, RenderingData, Sprite, SCREEN_HEIGHT, SCREEN_WIDTH};
 
 pub struct Renderer {
-    result: [u8; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4],
+    result: Box<[u8; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4]>,
 }
 impl Renderer {
     pub fn new() -> Self {
         Renderer {
-            result: [0xFF; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4],
+            result: Box::new([0xFF; SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize * 4]),
         }
     }
     pub fn render(&mut self, rendering_data: RenderingData) {
+        self.result.fill(0xFF);
         self.set_background(&rendering_data.background);
         self.set_sprites(&rendering_data.sprites);
-        render_canvas(&self.result);
+    }
+    pub fn result(&self) -> &[u8] {
+        &self.result[..]
+    }
+    // Puts back a picture taken from `result`.
+    pub fn restore(&mut self, result: &[u8]) -> () {
+        self.result.copy_from_slice(result);
     }
     fn set_background(&mut self, background: &Background) {
         for tile_row in 0..30 {
@@ -48,9 +52,9 @@ impl Renderer {
             }
         }
     }
-    fn set_sprites(&mut self, sprites: &Vec<Sprite>) {
+    fn set_sprites(&mut self, sprites: &[Sprite]) {
         for sprite in sprites.iter() {
-            self.set_sprite(&sprite);
+            self.set_sprite(sprite);
         }
     }
     fn set_sprite(&mut self, sprite: &Sprite) {
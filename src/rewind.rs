@@ -0,0 +1,222 @@
+use std::collections::VecDeque;
+
+// Snapshots of the machine taken every few frames, newest kept whole and the
+// older ones as run-length coded XORs against the snapshot after them. The
+// oldest are dropped once the deltas outgrow `capacity` bytes.
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    frame_count: u32,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    size: usize,
+}
+
+impl Rewind {
+    pub fn new(interval: u32, capacity: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            capacity,
+            frame_count: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    // Counts a finished frame and says whether a snapshot is due.
+    pub fn end_frame(&mut self) -> bool {
+        self.frame_count += 1;
+        if self.frame_count < self.interval {
+            return false;
+        }
+        self.frame_count = 0;
+        true
+    }
+    pub fn push(&mut self, snapshot: Vec<u8>) -> () {
+        if let Some(latest) = self.latest.take() {
+            let delta = encode_delta(&latest, &snapshot);
+            self.size += delta.len();
+            self.deltas.push_back(delta);
+            while self.size > self.capacity {
+                let Some(delta) = self.deltas.pop_front() else {
+                    break;
+                };
+                self.size -= delta.len();
+            }
+        }
+        self.latest = Some(snapshot);
+    }
+    // Drops the newest snapshot and returns the one before it, which stays
+    // as the newest.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.size -= delta.len();
+        let latest = self.latest.as_mut()?;
+        *latest = decode_delta(&delta, latest);
+        self.frame_count = 0;
+        self.latest.as_deref()
+    }
+    pub fn latest(&self) -> Option<&[u8]> {
+        self.latest.as_deref()
+    }
+    // How many snapshots can be stepped back to.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+    // Bytes held by the deltas.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+// The length of `old`, then runs of unchanged bytes as a LEB128 count followed
+// by a count and the XORed bytes of the changed ones.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let xor = |i: usize| old[i] ^ new.get(i).copied().unwrap_or(0);
+    let mut delta = Vec::new();
+    write_length(&mut delta, old.len());
+    let mut i = 0;
+    while i < old.len() {
+        let start = i;
+        while i < old.len() && xor(i) == 0 {
+            i += 1;
+        }
+        write_length(&mut delta, i - start);
+        let start = i;
+        while i < old.len() && xor(i) != 0 {
+            i += 1;
+        }
+        write_length(&mut delta, i - start);
+        delta.extend((start..i).map(xor));
+    }
+    delta
+}
+
+fn decode_delta(delta: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let length = read_length(delta, &mut position);
+    let mut old = new.to_vec();
+    old.resize(length, 0);
+    let mut i = 0;
+    while i < length {
+        i += read_length(delta, &mut position);
+        let count = read_length(delta, &mut position);
+        for byte in &delta[position..position + count] {
+            old[i] ^= byte;
+            i += 1;
+        }
+        position += count;
+    }
+    old
+}
+
+fn write_length(data: &mut Vec<u8>, mut length: usize) -> () {
+    while length >= 0x80 {
+        data.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    data.push(length as u8);
+}
+
+fn read_length(data: &[u8], position: &mut usize) -> usize {
+    let mut length = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        length |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return length;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::Bus, memory::MemorySpace, nes::NES};
+
+    #[test]
+    fn test_delta() {
+        let old = [vec![0x01; 300], vec![0x00; 10], vec![0x02; 5]].concat();
+        let mut new = old.clone();
+        new[3] = 0xFF;
+        new[200..210].fill(0x00);
+        new.push(0x03);
+        let delta = encode_delta(&old, &new);
+        assert!(delta.len() < 40);
+        assert_eq!(decode_delta(&delta, &new), old);
+        // lengths can differ both ways
+        assert_eq!(decode_delta(&encode_delta(&new, &old), &old), new);
+        assert_eq!(decode_delta(&encode_delta(&[], &old), &old), []);
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut rewind = Rewind::new(2, 14);
+        assert!(!rewind.end_frame());
+        assert!(rewind.end_frame());
+        for i in 0..5 {
+            rewind.push(vec![i; 4]);
+        }
+        // a delta is 7 bytes here, so only two fit
+        assert_eq!(rewind.len(), 2);
+        assert_eq!(rewind.size(), 14);
+        assert_eq!(rewind.pop(), Some(&[3; 4][..]));
+        assert_eq!(rewind.pop(), Some(&[2; 4][..]));
+        assert_eq!(rewind.pop(), None);
+        assert_eq!(rewind.latest(), Some(&[2; 4][..]));
+
+        rewind.push(vec![5; 4]);
+        assert_eq!(rewind.pop(), Some(&[2; 4][..]));
+    }
+
+    // $C000: INC $00; JMP $C000 with the picture on
+    #[test]
+    fn test_rewind() {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        data[0x10..0x15].copy_from_slice(&[0xE6, 0x00, 0x4C, 0x00, 0xC0]);
+        data[0x10 + 0x3FFC..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes = NES::new(&data);
+        nes.cpu_mut().bus_mut().write(0x2001, 0x0A);
+        assert!(!nes.rewind());
+
+        // the whole picture changes every frame
+        nes.set_rewinding(1, 4 << 20);
+        let mut counters = Vec::new();
+        for i in 0..10 {
+            // a different backdrop each frame
+            nes.poke(MemorySpace::PPU, 0x3F00, i);
+            nes.frame();
+            counters.push((
+                nes.peek(MemorySpace::CPU, 0).unwrap(),
+                nes.frame_buffer()[0],
+            ));
+        }
+        assert_eq!(nes.rewind_length(), 10);
+        for i in (0..9).rev() {
+            assert!(nes.rewind());
+            let counter = (
+                nes.peek(MemorySpace::CPU, 0).unwrap(),
+                nes.frame_buffer()[0],
+            );
+            assert_eq!(counter, counters[i]);
+        }
+        // to where rewinding was turned on
+        assert!(nes.rewind());
+        assert_eq!(nes.peek(MemorySpace::CPU, 0), Some(0));
+        assert!(!nes.rewind());
+
+        // and carries on from there
+        nes.frame();
+        assert_eq!(nes.rewind_length(), 1);
+        nes.set_rewinding(0, 0);
+        assert_eq!(nes.rewind_length(), 0);
+    }
+}
@@ -1,5 +1,3 @@
-use crate::log;
-
 pub struct ROM {
     data: Box<[u8]>,
 }
@@ -16,6 +14,13 @@ impl ROM {
         // }
         self.data[address as usize]
     }
+    // Patches the image, e.g. from a hex editor. Carts cannot do this.
+    pub fn write(&mut self, address: u16, data: u8) -> () {
+        self.data[address as usize] = data;
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
     pub fn size(&self) -> usize {
         self.data.len()
     }
@@ -34,6 +39,13 @@ mod tests {
         assert_eq!(rom.read(0x0003), 0x03);
     }
 
+    #[test]
+    fn test_write() {
+        let mut rom = ROM::new(vec![0x00, 0x01]);
+        rom.write(0x0001, 0xFF);
+        assert_eq!(rom.read(0x0001), 0xFF);
+    }
+
     #[test]
     fn test_size() {
         let rom = ROM::new(vec![0x00, 0x01, 0x02, 0x03]);
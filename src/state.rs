@@ -0,0 +1,187 @@
+use crate::{Byte, Word};
+
+// Save states start with the magic, the format version and a checksum of the
+// ROM file, then every component writes its fields in a fixed order. Bump the
+// version whenever that order or a field changes so old states are rejected.
+const MAGIC: &[u8; 4] = b"RNES";
+pub const VERSION: u32 = 1;
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_checksum: u32) -> Self {
+        let mut writer = StateWriter { data: Vec::new() };
+        writer.write_bytes(MAGIC);
+        writer.write_u32(VERSION);
+        writer.write_u32(rom_checksum);
+        writer
+    }
+    pub fn write_u8(&mut self, value: Byte) -> () {
+        self.data.push(value);
+    }
+    pub fn write_bool(&mut self, value: bool) -> () {
+        self.data.push(value as Byte);
+    }
+    pub fn write_u16(&mut self, value: Word) -> () {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn write_u32(&mut self, value: u32) -> () {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn write_u64(&mut self, value: u64) -> () {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+    // The reader has to know the length.
+    pub fn write_bytes(&mut self, data: &[u8]) -> () {
+        self.data.extend_from_slice(data);
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    // Checks the header before anything is read.
+    pub fn new(data: &'a [u8], rom_checksum: u32) -> Result<Self, String> {
+        let mut reader = StateReader { data, position: 0 };
+        if reader.read_bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err("not a save state".to_string());
+        }
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(format!(
+                "save state version {} is not supported, expected {}",
+                version, VERSION
+            ));
+        }
+        if reader.read_u32()? != rom_checksum {
+            return Err("save state is for another ROM".to_string());
+        }
+        Ok(reader)
+    }
+    pub fn read_u8(&mut self) -> Result<Byte, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+    pub fn read_u16(&mut self) -> Result<Word, String> {
+        Ok(Word::from_le_bytes(self.read_array()?))
+    }
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+    pub fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.position + length;
+        let data = self
+            .data
+            .get(self.position..end)
+            .ok_or_else(|| "save state is truncated".to_string())?;
+        self.position = end;
+        Ok(data)
+    }
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.read_bytes(N)?.try_into().unwrap())
+    }
+    pub fn finish(&self) -> Result<(), String> {
+        if self.position != self.data.len() {
+            return Err("save state has trailing data".to_string());
+        }
+        Ok(())
+    }
+}
+
+// For loaded indexes, rows and dots, which must stay below `end`.
+pub fn check_below<T: PartialOrd + std::fmt::Display>(
+    name: &str,
+    value: T,
+    end: T,
+) -> Result<T, String> {
+    if value >= end {
+        return Err(format!("save state has {} {} out of range", name, value));
+    }
+    Ok(value)
+}
+
+// CRC-32 as in zip and PNG, to tell ROMs apart.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = StateWriter::new(0x1234_5678);
+        writer.write_u8(0x01);
+        writer.write_bool(true);
+        writer.write_u16(0x0203);
+        writer.write_u32(0x0405_0607);
+        writer.write_u64(0x0809_0A0B_0C0D_0E0F);
+        writer.write_bytes(&[0x10, 0x11]);
+        let data = writer.into_bytes();
+
+        let mut reader = StateReader::new(&data, 0x1234_5678).unwrap();
+        assert_eq!(reader.read_u8(), Ok(0x01));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u16(), Ok(0x0203));
+        assert_eq!(reader.read_u32(), Ok(0x0405_0607));
+        assert!(reader.finish().is_err());
+        assert_eq!(reader.read_u64(), Ok(0x0809_0A0B_0C0D_0E0F));
+        assert_eq!(reader.read_array(), Ok([0x10, 0x11]));
+        assert_eq!(reader.read_u8(), Err("save state is truncated".to_string()));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn test_header() {
+        let data = StateWriter::new(0x1234_5678).into_bytes();
+        assert!(StateReader::new(&data, 0x1234_5678).is_ok());
+        assert_eq!(
+            StateReader::new(&data, 0).err(),
+            Some("save state is for another ROM".to_string())
+        );
+        assert_eq!(
+            StateReader::new(&data[1..], 0).err(),
+            Some("not a save state".to_string())
+        );
+        let mut old = data.clone();
+        old[4] = 0;
+        assert_eq!(
+            StateReader::new(&old, 0x1234_5678).err(),
+            Some(format!(
+                "save state version 0 is not supported, expected {}",
+                VERSION
+            ))
+        );
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Word;
+
+struct Symbol {
+    name: String,
+    size: Word,
+}
+
+// Labels for CPU addresses, loaded from assembler or emulator symbol files.
+#[derive(Default)]
+pub struct Symbols {
+    labels: BTreeMap<Word, Symbol>,
+    addresses: HashMap<String, Word>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    // Picks the format from the file extension. PRG ROM offsets in Mesen
+    // files are placed at the top of the address space.
+    pub fn load(
+        &mut self,
+        file_name: &str,
+        text: &str,
+        program_rom_size: usize,
+    ) -> Result<(), String> {
+        let extension = file_name.rsplit('.').next().unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "dbg" => self.load_dbg(text),
+            "nl" => self.load_nl(text),
+            "mlb" => self.load_mlb(text, program_rom_size),
+            _ => Err(format!("unknown symbol file: {}", file_name)),
+        }
+    }
+
+    pub fn add(&mut self, address: Word, name: &str, size: Word) -> () {
+        self.addresses.entry(name.to_string()).or_insert(address);
+        self.labels.insert(
+            address,
+            Symbol {
+                name: name.to_string(),
+                size: size.max(1),
+            },
+        );
+    }
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+    pub fn address(&self, name: &str) -> Option<Word> {
+        self.addresses.get(name).copied()
+    }
+    pub fn label(&self, address: Word) -> Option<&str> {
+        self.labels.get(&address).map(|symbol| symbol.name.as_str())
+    }
+    // Names an address as `label` or `label+offset`. Offsets stay within a
+    // symbol's size, except in ROM where they run up to the next label so
+    // code inside a routine is named after it.
+    pub fn describe(&self, address: Word) -> Option<String> {
+        let (start, symbol) = self.labels.range(..=address).next_back()?;
+        let offset = address - start;
+        if offset == 0 {
+            Some(symbol.name.clone())
+        } else if offset < symbol.size || (*start >= 0x8000 && address >= 0x8000) {
+            Some(format!("{}+{}", symbol.name, offset))
+        } else {
+            None
+        }
+    }
+
+    // ca65/ld65 debug info: `sym id=0,name="main",...,val=0xC000,type=lab`
+    pub fn load_dbg(&mut self, text: &str) -> Result<(), String> {
+        for line in text.lines() {
+            let Some(fields) = line.strip_prefix("sym\t").or(line.strip_prefix("sym ")) else {
+                continue;
+            };
+            let fields = fields
+                .split(',')
+                .filter_map(|field| field.split_once('='))
+                .collect::<HashMap<_, _>>();
+            // equates are constants rather than places in memory
+            if fields.get("type") != Some(&"lab") {
+                continue;
+            }
+            let (Some(name), Some(value)) = (fields.get("name"), fields.get("val")) else {
+                return Err(format!("invalid symbol: {}", line));
+            };
+            let address = parse_hex(value.trim_start_matches("0x"), line)?;
+            let size = match fields.get("size") {
+                Some(size) => size
+                    .parse()
+                    .map_err(|_| format!("invalid symbol: {}", line))?,
+                None => 1,
+            };
+            self.add(address, name.trim_matches('"'), size);
+        }
+        Ok(())
+    }
+
+    // FCEUX name lists: `$C000#main#comment`, `$0300/10#buffer#`
+    pub fn load_nl(&mut self, text: &str) -> Result<(), String> {
+        for line in text.lines() {
+            let Some(line) = line.trim().strip_prefix('$') else {
+                continue;
+            };
+            let mut fields = line.split('#');
+            let location = fields.next().unwrap_or("");
+            let name = fields.next().unwrap_or("");
+            if name.is_empty() {
+                continue;
+            }
+            let (address, size) = match location.split_once('/') {
+                Some((address, size)) => (address, parse_hex(size, line)?),
+                None => (location, 1),
+            };
+            self.add(parse_hex(address, line)?, name, size);
+        }
+        Ok(())
+    }
+
+    // Mesen labels: `P:1F2:name:comment` with ranges like `R:300-30F:buffer`,
+    // also with Mesen 2 memory type names.
+    pub fn load_mlb(&mut self, text: &str, program_rom_size: usize) -> Result<(), String> {
+        let program_rom_start = 0x10000 - program_rom_size.min(0x8000);
+        for line in text.lines() {
+            let mut fields = line.trim().split(':');
+            let (Some(kind), Some(offset), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let (start, end) = match offset.split_once('-') {
+                Some((start, end)) => (parse_hex(start, line)?, parse_hex(end, line)?),
+                None => (parse_hex(offset, line)?, parse_hex(offset, line)?),
+            };
+            let base = match kind {
+                "P" | "NesPrgRom" => program_rom_start,
+                "R" | "NesInternalRam" | "G" | "NesMemory" => 0x0000,
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => 0x6000,
+                _ => continue,
+            };
+            let address = (base + start as usize) as Word;
+            self.add(address, name, end.wrapping_sub(start).wrapping_add(1));
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(text: &str, line: &str) -> Result<Word, String> {
+    Word::from_str_radix(text.trim(), 16).map_err(|_| format!("invalid symbol: {}", line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut symbols = Symbols::new();
+        symbols.add(0xC000, "main", 1);
+        symbols.add(0xC4EF, "player_update", 1);
+        symbols.add(0x0300, "buffer", 16);
+        symbols.add(0x0010, "player_x", 1);
+        assert_eq!(symbols.describe(0xC4EF), Some("player_update".to_string()));
+        assert_eq!(
+            symbols.describe(0xC4F2),
+            Some("player_update+3".to_string())
+        );
+        assert_eq!(symbols.describe(0xC4EE), Some("main+1262".to_string()));
+        assert_eq!(symbols.describe(0x030F), Some("buffer+15".to_string()));
+        assert_eq!(symbols.describe(0x0310), None);
+        assert_eq!(symbols.describe(0x0011), None);
+        assert_eq!(symbols.describe(0x0000), None);
+        assert_eq!(symbols.address("player_x"), Some(0x0010));
+        assert_eq!(symbols.label(0x0300), Some("buffer"));
+    }
+
+    #[test]
+    fn test_load_dbg() {
+        let text = "version\tmajor=2,minor=0\n\
+            sym\tid=0,name=\"main\",addrsize=absolute,scope=0,def=1,ref=4,val=0xC000,seg=1,type=lab\n\
+            sym\tid=1,name=\"PPUCTRL\",addrsize=absolute,scope=0,def=2,val=0x2000,type=equ\n\
+            sym\tid=2,name=\"buffer\",addrsize=absolute,size=16,scope=0,def=3,val=0x300,seg=2,type=lab\n";
+        let mut symbols = Symbols::new();
+        symbols.load("game.dbg", text, 0x8000).unwrap();
+        assert_eq!(symbols.address("main"), Some(0xC000));
+        assert_eq!(symbols.address("PPUCTRL"), None);
+        assert_eq!(symbols.describe(0x030A), Some("buffer+10".to_string()));
+    }
+
+    #[test]
+    fn test_load_nl() {
+        let text = "$C000#main#entry point\n$0300/10#buffer#\n$C010##\n";
+        let mut symbols = Symbols::new();
+        symbols.load("game.nes.0.nl", text, 0x8000).unwrap();
+        assert_eq!(symbols.address("main"), Some(0xC000));
+        assert_eq!(symbols.describe(0x030F), Some("buffer+15".to_string()));
+        assert_eq!(symbols.label(0xC010), None);
+        assert_eq!(
+            symbols.load_nl("$XYZ#bad#"),
+            Err("invalid symbol: XYZ#bad#".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_mlb() {
+        let text = "P:0010:reset:entry\nR:0300-030F:buffer\nNesPrgRom:0020:nmi\nS:0000:save\n";
+        let mut symbols = Symbols::new();
+        symbols.load("game.mlb", text, 0x4000).unwrap();
+        assert_eq!(symbols.address("reset"), Some(0xC010));
+        assert_eq!(symbols.address("nmi"), Some(0xC020));
+        assert_eq!(symbols.address("save"), Some(0x6000));
+        assert_eq!(symbols.describe(0x030F), Some("buffer+15".to_string()));
+
+        let mut symbols = Symbols::new();
+        symbols.load_mlb(text, 0x8000).unwrap();
+        assert_eq!(symbols.address("reset"), Some(0x8010));
+        assert!(symbols.load("game.sym", text, 0x8000).is_err());
+    }
+}
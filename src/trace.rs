@@ -0,0 +1,188 @@
+use crate::{
+    cpu::{
+        disassembler::Instruction,
+        opcode::{Addressing, OpcodeBaseName},
+        Bus, CPU,
+    },
+    nes::NES,
+    symbols::Symbols,
+    Word,
+};
+
+mod tracer;
+
+pub use tracer::{TraceEntry, TraceFilter, Tracer};
+
+// Formats the instruction at PC and the state before it runs, laid out like
+// the nestest.log golden log.
+pub fn nestest_line(nes: &NES) -> String {
+    format_line(nes, None)
+}
+
+// The nestest layout with operands named by the symbols and the routine the
+// PC is in up front, e.g. `player_update+3      C4F2  A5 10     LDA player_x`.
+pub fn labeled_line(nes: &NES, symbols: &Symbols) -> String {
+    let pc = nes.cpu().register().get_pc();
+    let label = symbols.describe(pc).unwrap_or_default();
+    format!("{:<20} {}", label, format_line(nes, Some(symbols)))
+}
+
+fn format_line(nes: &NES, symbols: Option<&Symbols>) -> String {
+    let cpu = nes.cpu();
+    let register = cpu.register();
+    let instruction = cpu.disassemble(register.get_pc());
+    let bytes = instruction
+        .bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let marker = if instruction.is_unofficial() {
+        '*'
+    } else {
+        ' '
+    };
+    // B and bit 5 are not stored in P, they only exist on the stack
+    let p = (register.get_p() & 0xEF) | 0x20;
+    let ppu = nes.ppu();
+    format!(
+        "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        instruction.address,
+        bytes,
+        marker,
+        annotate(cpu, &instruction, symbols),
+        register.get_a(),
+        register.get_x(),
+        register.get_y(),
+        p,
+        register.get_s(),
+        ppu.scanline(),
+        ppu.dot(),
+        cpu.cycle()
+    )
+}
+
+// Appends the addresses and values the instruction touches the way
+// Nintendulator prints them.
+fn annotate<B: Bus>(cpu: &CPU<B>, instruction: &Instruction, symbols: Option<&Symbols>) -> String {
+    let text = &match symbols {
+        Some(symbols) => instruction.text_with(|address| symbols.describe(address)),
+        None => instruction.text.clone(),
+    };
+    let Some(address) = instruction.effective_address else {
+        return text.clone();
+    };
+    let value = cpu.bus().peek(address);
+    match instruction.opcode.addressing {
+        Addressing::ZeroPage => format!("{} = {:02X}", text, value),
+        Addressing::Absolute => match instruction.opcode.base_name {
+            OpcodeBaseName::JMP | OpcodeBaseName::JSR => text.clone(),
+            _ => format!("{} = {:02X}", text, value),
+        },
+        Addressing::ZeroPageX | Addressing::ZeroPageY => {
+            format!("{} @ {:02X} = {:02X}", text, address, value)
+        }
+        Addressing::AbsoluteX | Addressing::AbsoluteY => {
+            format!("{} @ {:04X} = {:02X}", text, address, value)
+        }
+        Addressing::Indirect => format!("{} = {:04X}", text, address),
+        Addressing::IndirectX => {
+            let pointer = instruction.operand()[0].wrapping_add(cpu.register().get_x());
+            format!(
+                "{} @ {:02X} = {:04X} = {:02X}",
+                text, pointer, address, value
+            )
+        }
+        Addressing::IndirectY => {
+            let base = address.wrapping_sub(cpu.register().get_y() as Word);
+            format!("{} = {:04X} @ {:04X} = {:02X}", text, base, address, value)
+        }
+        _ => text.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Byte;
+
+    fn prepare_nes(program: &[Byte]) -> NES {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        data[0x10..0x10 + program.len()].copy_from_slice(program);
+        let mut nes = NES::new(&data);
+        nes.cpu_mut().get_register().set_pc(0xC000);
+        nes
+    }
+
+    #[test]
+    fn test_nestest_line() {
+        let nes = prepare_nes(&[0x4C, 0xF5, 0xC5]);
+        assert_eq!(
+            nestest_line(&nes),
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+    }
+
+    #[test]
+    fn test_operands() {
+        // LDA ($80,X); LDA ($89),Y; NOP $A9; STA $0300,X; JMP ($02FF)
+        let mut nes = prepare_nes(&[
+            0xA1, 0x80, 0xB1, 0x89, 0x04, 0xA9, 0x9D, 0x00, 0x03, 0x6C, 0xFF, 0x02,
+        ]);
+        {
+            let cpu = nes.cpu_mut();
+            cpu.get_register().set_x(0x01);
+            cpu.get_register().set_y(0x05);
+            let bus = cpu.bus_mut();
+            for (address, data) in [
+                (0x0081, 0x00),
+                (0x0082, 0x02),
+                (0x0089, 0x00),
+                (0x008A, 0x03),
+                (0x0200, 0x5A),
+                (0x0305, 0x89),
+                (0x02FF, 0x7E),
+                (0x0300, 0xDB),
+            ] {
+                bus.write(address, data);
+            }
+        }
+        let texts = [
+            "LDA ($80,X) @ 81 = 0200 = 5A",
+            "LDA ($89),Y = 0300 @ 0305 = 89",
+            "NOP $A9 = 00",
+            "STA $0300,X @ 0301 = 00",
+            "JMP ($02FF) = 5A7E",
+        ];
+        let mut pc = 0xC000;
+        for text in texts {
+            let instruction = nes.cpu().disassemble(pc);
+            assert_eq!(annotate(nes.cpu(), &instruction, None), text);
+            pc += instruction.length() as Word;
+        }
+
+        nes.cpu_mut().get_register().set_pc(0xC004);
+        assert_eq!(&nestest_line(&nes)[..28], "C004  04 A9    *NOP $A9 = 00");
+    }
+
+    #[test]
+    fn test_labeled_line() {
+        // JSR $C010; LDA $0300,X
+        let mut nes = prepare_nes(&[0x20, 0x10, 0xC0, 0xBD, 0x00, 0x03]);
+        let mut symbols = Symbols::new();
+        symbols.add(0xC000, "main", 1);
+        symbols.add(0xC010, "player_update", 1);
+        symbols.add(0x0300, "buffer", 16);
+        assert_eq!(
+            &labeled_line(&nes, &symbols)[..60],
+            "main                 C000  20 10 C0  JSR player_update      "
+        );
+        nes.cpu_mut().get_register().set_pc(0xC003);
+        assert_eq!(
+            &labeled_line(&nes, &symbols)[..61],
+            "main+3               C003  BD 00 03  LDA buffer,X @ 0300 = 00"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use std::{collections::VecDeque, fmt, io::Write};
+
+use crate::{
+    debugger::{AccessKind, MemoryAccess},
+    interrupt::InterruptKind,
+    log,
+    nes::NES,
+    symbols::Symbols,
+    Byte, Cycle, Word,
+};
+
+// The state right before an instruction runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub pc: Word,
+    pub text: String,
+    pub a: Byte,
+    pub x: Byte,
+    pub y: Byte,
+    pub p: Byte,
+    pub s: Byte,
+    pub cycle: u64,
+    pub scanline: u16,
+    pub dot: Cycle,
+    pub effective_address: Option<Word>,
+    // what the instruction wrote at the effective address, or read when it
+    // wrote nothing there
+    pub value: Option<Byte>,
+    // the interrupt taken right before the instruction
+    pub interrupt: Option<InterruptKind>,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(interrupt) = self.interrupt {
+            writeln!(f, "-- {:?} --", interrupt)?;
+        }
+        write!(
+            f,
+            "{:04X}  {:<24} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{} SL:{} DOT:{}",
+            self.pc,
+            self.text,
+            self.a,
+            self.x,
+            self.y,
+            self.p,
+            self.s,
+            self.cycle,
+            self.scanline,
+            self.dot
+        )?;
+        if let (Some(address), Some(value)) = (self.effective_address, self.value) {
+            write!(f, " [{:04X}]={:02X}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+// Every filter has to pass for an instruction to be recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFilter {
+    // PCs from the first to the second address, both included
+    Range(Word, Word),
+    // from the first time the breakpoint with the id is hit on
+    AfterBreakpoint(usize),
+    NMIHandler,
+}
+
+enum Output {
+    Buffer(VecDeque<TraceEntry>, usize),
+    Writer(Box<dyn Write>),
+}
+
+// Records executed instructions into a ring buffer of the last ones, or
+// writes them out as lines as they come.
+pub struct Tracer {
+    output: Output,
+    filters: Vec<TraceFilter>,
+    hit_breakpoints: Vec<usize>,
+    // S right above each NMI frame the handler is running in
+    nmi_stack: Vec<Byte>,
+    // recorded, waiting for the accesses the instruction makes
+    pending: Option<TraceEntry>,
+}
+
+impl Tracer {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Tracer::new(Output::Buffer(VecDeque::with_capacity(capacity), capacity))
+    }
+    pub fn with_writer(writer: Box<dyn Write>) -> Self {
+        Tracer::new(Output::Writer(writer))
+    }
+    fn new(output: Output) -> Self {
+        Tracer {
+            output,
+            filters: Vec::new(),
+            hit_breakpoints: Vec::new(),
+            nmi_stack: Vec::new(),
+            pending: None,
+        }
+    }
+
+    pub fn add_filter(&mut self, filter: TraceFilter) -> () {
+        self.filters.push(filter);
+    }
+    // Oldest first. Empty when writing out.
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        let entries = match &self.output {
+            Output::Buffer(entries, _) => Some(entries.iter()),
+            Output::Writer(_) => None,
+        };
+        entries.into_iter().flatten()
+    }
+    pub fn clear(&mut self) -> () {
+        if let Output::Buffer(entries, _) = &mut self.output {
+            entries.clear();
+        }
+    }
+    // The buffered entries one per line, e.g. to look at what led to a crash.
+    pub fn dump(&self) -> String {
+        self.entries()
+            .map(|entry| format!("{}\n", entry))
+            .collect::<String>()
+    }
+
+    pub fn hit_breakpoint(&mut self, id: usize) -> () {
+        if !self.hit_breakpoints.contains(&id) {
+            self.hit_breakpoints.push(id);
+        }
+    }
+
+    // Records the instruction at PC, after the CPU has serviced `interrupt`.
+    // The entry goes out with `complete` once the instruction has run.
+    pub fn record(&mut self, nes: &NES, interrupt: Option<InterruptKind>, symbols: &Symbols) -> () {
+        if let Some(entry) = self.pending.take() {
+            self.output(entry);
+        }
+        let cpu = nes.cpu();
+        let register = cpu.register();
+        let s = register.get_s();
+        // a handler is done once RTI has pulled its frame back off
+        while self.nmi_stack.last().is_some_and(|top| s >= *top) {
+            self.nmi_stack.pop();
+        }
+        if interrupt == Some(InterruptKind::NMI) {
+            self.nmi_stack.push(s.wrapping_add(3));
+        }
+        let pc = register.get_pc();
+        if !self.filters.iter().all(|filter| self.passes(*filter, pc)) {
+            return;
+        }
+
+        let instruction = cpu.disassemble(pc);
+        let text = if symbols.is_empty() {
+            instruction.text.clone()
+        } else {
+            instruction.text_with(|address| symbols.describe(address))
+        };
+        let ppu = nes.ppu();
+        let entry = TraceEntry {
+            pc,
+            text,
+            a: register.get_a(),
+            x: register.get_x(),
+            y: register.get_y(),
+            p: register.get_p(),
+            s,
+            cycle: cpu.cycle(),
+            scanline: ppu.scanline(),
+            dot: ppu.dot(),
+            effective_address: instruction.effective_address,
+            value: None,
+            interrupt,
+        };
+        self.pending = Some(entry);
+    }
+    // Adds the value from the CPU accesses the recorded instruction made. A
+    // read-modify-write writes the old value back first, so the last write
+    // is the one kept.
+    pub fn complete(&mut self, accesses: &[MemoryAccess]) -> () {
+        let Some(mut entry) = self.pending.take() else {
+            return;
+        };
+        if let Some(address) = entry.effective_address {
+            let accesses = accesses
+                .iter()
+                .filter(|access| access.address == address)
+                .collect::<Vec<_>>();
+            entry.value = accesses
+                .iter()
+                .rev()
+                .find(|access| access.kind == AccessKind::Write)
+                .or(accesses.last())
+                .map(|access| access.data);
+        }
+        self.output(entry);
+    }
+
+    fn output(&mut self, entry: TraceEntry) -> () {
+        match &mut self.output {
+            Output::Buffer(entries, capacity) => {
+                if entries.len() == *capacity {
+                    entries.pop_front();
+                }
+                if *capacity > 0 {
+                    entries.push_back(entry);
+                }
+            }
+            Output::Writer(writer) => {
+                if let Err(error) = writeln!(writer, "{}", entry) {
+                    log(&format!("trace write failed: {}", error));
+                    self.output = Output::Buffer(VecDeque::new(), 0);
+                }
+            }
+        }
+    }
+
+    fn passes(&self, filter: TraceFilter, pc: Word) -> bool {
+        match filter {
+            TraceFilter::Range(start, end) => start <= pc && pc <= end,
+            TraceFilter::AfterBreakpoint(id) => self.hit_breakpoints.contains(&id),
+            TraceFilter::NMIHandler => !self.nmi_stack.is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    // $C000: LDA #$80; STA $2000 (NMI on); INX; JMP $C005
+    // $C100: INY; RTI (NMI handler)
+    fn prepare_nes() -> NES {
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        let program = [0xA9, 0x80, 0x8D, 0x00, 0x20, 0xE8, 0x4C, 0x05, 0xC0];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        data[0x110..0x112].copy_from_slice(&[0xC8, 0x40]);
+        data[0x10 + 0x3FFA..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC1, 0x00, 0xC0]);
+        NES::new(&data)
+    }
+
+    fn run(nes: &mut NES, tracer: &mut Tracer, count: usize) -> () {
+        let symbols = Symbols::new();
+        nes.cpu_mut().bus_mut().set_access_logging(true);
+        for _ in 0..count {
+            let interrupt = nes.cpu_mut().service_interrupts();
+            tracer.record(nes, interrupt, &symbols);
+            nes.step();
+            let accesses = nes.cpu_mut().bus_mut().take_accesses();
+            tracer.complete(&accesses);
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut nes = prepare_nes();
+        let mut tracer = Tracer::with_capacity(3);
+        run(&mut nes, &mut tracer, 5);
+        let pcs = tracer.entries().map(|entry| entry.pc).collect::<Vec<_>>();
+        assert_eq!(pcs, [0xC005, 0xC006, 0xC005]);
+        let entry = tracer.entries().next().unwrap();
+        assert_eq!(entry.text, "INX");
+        assert_eq!(entry.a, 0x80);
+        assert_eq!(entry.cycle, 13);
+        assert_eq!(tracer.dump().lines().count(), 3);
+
+        tracer.clear();
+        assert_eq!(tracer.entries().count(), 0);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut nes = prepare_nes();
+        let mut tracer = Tracer::with_capacity(100);
+        tracer.add_filter(TraceFilter::Range(0xC000, 0xC002));
+        run(&mut nes, &mut tracer, 10);
+        let pcs = tracer.entries().map(|entry| entry.pc).collect::<Vec<_>>();
+        assert_eq!(pcs, [0xC000, 0xC002]);
+        let entry = tracer.entries().nth(1).unwrap();
+        assert_eq!(entry.effective_address, Some(0x2000));
+
+        // only the handler, across a whole frame and a bit
+        let mut nes = prepare_nes();
+        let mut tracer = Tracer::with_capacity(100);
+        tracer.add_filter(TraceFilter::NMIHandler);
+        run(&mut nes, &mut tracer, 20000);
+        let entries = tracer.entries().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].pc, 0xC100);
+        assert_eq!(entries[0].interrupt, Some(InterruptKind::NMI));
+        assert_eq!(entries[0].scanline, 241);
+        assert_eq!(entries[1].pc, 0xC101);
+        assert_eq!(entries[1].interrupt, None);
+    }
+
+    #[test]
+    fn test_values() {
+        // $C000: LDA #$05; STA $0300; INC $0300; LDX $0300; JMP $C00B
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+        let mut data = [&header[..], &[0x00; 8], &[0x00; 0x4000], &[0x00; 0x2000]].concat();
+        let program = [
+            0xA9, 0x05, 0x8D, 0x00, 0x03, 0xEE, 0x00, 0x03, 0xAE, 0x00, 0x03, 0x4C, 0x0B, 0xC0,
+        ];
+        data[0x10..0x10 + program.len()].copy_from_slice(&program);
+        data[0x10 + 0x3FFC..0x10 + 0x3FFE].copy_from_slice(&[0x00, 0xC0]);
+        let mut nes = NES::new(&data);
+        let mut tracer = Tracer::with_capacity(10);
+        run(&mut nes, &mut tracer, 4);
+        let values = tracer
+            .entries()
+            .map(|entry| entry.value)
+            .collect::<Vec<_>>();
+        // the value stored, the value after the increment and the value loaded
+        assert_eq!(values, [None, Some(0x05), Some(0x06), Some(0x06)]);
+    }
+
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut nes = prepare_nes();
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut tracer = Tracer::with_writer(Box::new(SharedWriter(output.clone())));
+        run(&mut nes, &mut tracer, 2);
+        assert_eq!(tracer.entries().count(), 0);
+        assert_eq!(
+            String::from_utf8(output.borrow().clone()).unwrap(),
+            "C000  LDA #$80                 A:00 X:00 Y:00 P:24 SP:FD CYC:7 SL:0 DOT:21\n\
+             C002  STA $2000                A:80 X:00 Y:00 P:A4 SP:FD CYC:9 SL:0 DOT:27 [2000]=80\n"
+        );
+    }
+}
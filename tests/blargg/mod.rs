@@ -0,0 +1,70 @@
+// Runs blargg's test ROMs, which report through PRG RAM: $6000 is $80 while
+// a test runs, $81 when it wants the console reset and the result code once
+// it is done, with DE B0 61 at $6001-$6003 and the message text from $6004.
+//
+// The ROMs are not distributed with this crate. Check out
+// https://github.com/christopherpow/nes-test-roms into static/nes, where the
+// ROM list in static/index.html expects it, then run these tests with
+// `cargo test -- --ignored`.
+
+use rust_nes::{memory::MemorySpace, nes::NES, Byte};
+
+pub const ROM_DIRECTORY: &str = "static/nes/nes-test-roms";
+const MAX_FRAMES: usize = 60 * 60;
+// the ROMs want the reset at least 100 ms after asking for it
+const RESET_DELAY: usize = 10;
+const SIGNATURE: [Byte; 3] = [0xDE, 0xB0, 0x61];
+
+// Checks every ROM under `ROM_DIRECTORY`.
+pub fn check(paths: &[&str]) -> () {
+    let mut failures = Vec::new();
+    for path in paths {
+        let path = format!("{}/{}", ROM_DIRECTORY, path);
+        let rom = std::fs::read(&path).unwrap_or_else(|error| panic!("{}: {}", path, error));
+        let (result, message) = run(&rom, &path);
+        if result != 0 || !message.contains("Passed") {
+            failures.push(format!(
+                "{}: result {:02X}, {}",
+                path,
+                result,
+                message.trim()
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+fn run(rom: &[u8], path: &str) -> (Byte, String) {
+    let mut nes = NES::new(rom);
+    let peek = |nes: &NES, address: usize| nes.peek(MemorySpace::CPU, address).unwrap();
+    let mut reset_at = None;
+    for frame in 0..MAX_FRAMES {
+        nes.frame();
+        if (0..3).map(|i| peek(&nes, 0x6001 + i)).ne(SIGNATURE) {
+            continue;
+        }
+        match peek(&nes, 0x6000) {
+            0x80 => {}
+            0x81 => match reset_at {
+                None => reset_at = Some(frame + RESET_DELAY),
+                Some(at) if frame >= at => {
+                    nes.reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+            },
+            result => {
+                let message = (0x6004..0x8000)
+                    .map(|address| peek(&nes, address))
+                    .take_while(|&byte| byte != 0)
+                    .map(|byte| byte as char)
+                    .collect();
+                return (result, message);
+            }
+        }
+    }
+    panic!(
+        "{} gave no result at $6000 after {} frames",
+        path, MAX_FRAMES
+    );
+}
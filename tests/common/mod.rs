@@ -0,0 +1,54 @@
+use std::{cell::RefCell, rc::Rc};
+
+use rust_nes::{
+    cpu::{Bus, CPU},
+    interrupt::Interrupt,
+    Byte, Word,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+// 64K of plain RAM that can record every access the CPU makes
+pub struct FlatBus {
+    pub memory: Vec<Byte>,
+    pub log: Vec<(Word, Byte, Access)>,
+    pub is_logging: bool,
+}
+
+impl FlatBus {
+    pub fn new() -> Self {
+        FlatBus {
+            memory: vec![0x00; 0x10000],
+            log: Vec::new(),
+            is_logging: false,
+        }
+    }
+}
+
+impl Bus for FlatBus {
+    fn read(&mut self, address: Word) -> Byte {
+        let data = self.memory[address as usize];
+        if self.is_logging {
+            self.log.push((address, data, Access::Read));
+        }
+        data
+    }
+    fn peek(&self, address: Word) -> Byte {
+        self.memory[address as usize]
+    }
+    fn write(&mut self, address: Word, data: Byte) -> () {
+        self.memory[address as usize] = data;
+        if self.is_logging {
+            self.log.push((address, data, Access::Write));
+        }
+    }
+    fn tick(&mut self) -> () {}
+}
+
+pub fn prepare_cpu(bus: FlatBus) -> CPU<FlatBus> {
+    CPU::new(bus, Rc::new(RefCell::new(Interrupt::default())))
+}
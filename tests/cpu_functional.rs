@@ -0,0 +1,48 @@
+// Runs Klaus Dormann's 6502 functional test on a flat 64K bus.
+//
+// The binary is not distributed with this crate. Assemble it from
+// https://github.com/Klaus2m5/6502_65C02_functional_tests (or take the
+// prebuilt bin_files/6502_functional_test.bin) and place it at
+// tests/data/6502_functional_test.bin, then run it with
+// `cargo test -- --ignored`.
+
+mod common;
+
+use common::{prepare_cpu, FlatBus};
+use rust_nes::{cpu::CpuVariant, Word};
+
+const PATH: &str = "tests/data/6502_functional_test.bin";
+const START: Word = 0x0400;
+// the prebuilt binary loops here once every test has passed
+const SUCCESS: Word = 0x3469;
+const MAX_INSTRUCTIONS: usize = 100_000_000;
+
+#[test]
+#[ignore = "needs tests/data/6502_functional_test.bin, see the top of this file"]
+fn test_functional() {
+    let image = std::fs::read(PATH).unwrap_or_else(|error| panic!("{}: {}", PATH, error));
+    let mut bus = FlatBus::new();
+    bus.memory[..image.len()].copy_from_slice(&image);
+    let mut cpu = prepare_cpu(bus);
+    // the test covers decimal mode, which the 2A03 does not have
+    cpu.set_variant(CpuVariant::NMOS6502);
+    cpu.get_register().set_pc(START);
+
+    // every failure ends in a branch or jump to itself
+    for _ in 0..MAX_INSTRUCTIONS {
+        let pc = cpu.register().get_pc();
+        cpu.run();
+        if cpu.register().get_pc() == pc {
+            assert_eq!(
+                pc,
+                SUCCESS,
+                "trapped at {:04X} after {} cycles (test case {:02X})",
+                pc,
+                cpu.cycle(),
+                cpu.bus().memory[0x0200]
+            );
+            return;
+        }
+    }
+    panic!("no trap after {} instructions", MAX_INSTRUCTIONS);
+}
@@ -0,0 +1,31 @@
+// Compares the CPU against the nestest golden log in automation mode.
+//
+// Neither file is distributed with this crate. Take nestest.nes and
+// nestest.log from https://www.qmtpro.com/~nes/misc/ and place them in
+// tests/data, then run it with `cargo test -- --ignored`.
+
+use rust_nes::{nes::NES, trace};
+
+const ROM_PATH: &str = "tests/data/nestest.nes";
+const LOG_PATH: &str = "tests/data/nestest.log";
+
+#[test]
+#[ignore = "needs nestest.nes and nestest.log in tests/data, see the top of this file"]
+fn test_nestest() {
+    let rom = std::fs::read(ROM_PATH).unwrap_or_else(|error| panic!("{}: {}", ROM_PATH, error));
+    let log =
+        std::fs::read_to_string(LOG_PATH).unwrap_or_else(|error| panic!("{}: {}", LOG_PATH, error));
+    let mut nes = NES::new(&rom);
+    // automation mode runs every test from $C000 without needing the PPU
+    nes.cpu_mut().get_register().set_pc(0xC000);
+    for (i, expected) in log.lines().enumerate() {
+        let actual = trace::nestest_line(&nes);
+        assert_eq!(
+            actual,
+            expected.trim_end(),
+            "first mismatch on line {}",
+            i + 1
+        );
+        nes.step();
+    }
+}
@@ -0,0 +1,13 @@
+// Checks OAMADDR/OAMDATA behavior against oam3 from the ROM list. See
+// tests/blargg/mod.rs for where to get the ROMs.
+//
+// read2004 is not here: it reads $2004 through sprite evaluation and fetches,
+// which are not emulated.
+
+mod blargg;
+
+#[test]
+#[ignore = "needs the nes-test-roms checkout, see tests/blargg/mod.rs"]
+fn test_oam() {
+    blargg::check(&["other/oam3.nes"]);
+}
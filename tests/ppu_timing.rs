@@ -0,0 +1,24 @@
+// Checks VBlank and NMI timing against blargg's ppu_vbl_nmi singles. See
+// tests/blargg/mod.rs for where to get the ROMs.
+//
+// The nmi_sync demos are not here: they only draw a picture and never report
+// a result at $6000.
+
+mod blargg;
+
+#[test]
+#[ignore = "needs the nes-test-roms checkout, see tests/blargg/mod.rs"]
+fn test_ppu_vbl_nmi() {
+    blargg::check(&[
+        "ppu_vbl_nmi/rom_singles/01-vbl_basics.nes",
+        "ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes",
+        "ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes",
+        "ppu_vbl_nmi/rom_singles/04-nmi_control.nes",
+        "ppu_vbl_nmi/rom_singles/05-nmi_timing.nes",
+        "ppu_vbl_nmi/rom_singles/06-suppression.nes",
+        "ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes",
+        "ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes",
+        "ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes",
+        "ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes",
+    ]);
+}
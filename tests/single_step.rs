@@ -0,0 +1,188 @@
+// Replays the per-opcode SingleStepTests vectors against the CPU.
+//
+// The vectors are not distributed with this crate. Download the nes6502
+// set from https://github.com/SingleStepTests/65x02 and place the
+// 00.json ... ff.json files in tests/data/single_step, then run it with
+// `cargo test -- --ignored`.
+
+mod common;
+
+use common::{prepare_cpu, Access, FlatBus};
+use rust_nes::{
+    cpu::{
+        opcode::{get_opcode, OpcodeBaseName},
+        Bus, CPU,
+    },
+    Byte, Word,
+};
+use serde_json::Value;
+
+const DIRECTORY: &str = "tests/data/single_step";
+// B and bit 5 only exist on the stack
+const FLAG_MASK: Byte = 0xCF;
+
+fn number(value: &Value) -> u64 {
+    value.as_u64().expect("number")
+}
+
+fn load_state(cpu: &mut CPU<FlatBus>, state: &Value) -> () {
+    let register = cpu.get_register();
+    register.set_pc(number(&state["pc"]) as Word);
+    register.set_s(number(&state["s"]) as Byte);
+    register.set_a(number(&state["a"]) as Byte);
+    register.set_x(number(&state["x"]) as Byte);
+    register.set_y(number(&state["y"]) as Byte);
+    register.set_p(number(&state["p"]) as Byte);
+    for cell in state["ram"].as_array().expect("ram") {
+        let address = number(&cell[0]) as Word;
+        cpu.bus_mut().write(address, number(&cell[1]) as Byte);
+    }
+}
+
+fn compare_state(cpu: &CPU<FlatBus>, state: &Value) -> Result<(), String> {
+    let register = cpu.register();
+    let registers = [
+        ("pc", register.get_pc() as u64, number(&state["pc"])),
+        ("s", register.get_s() as u64, number(&state["s"])),
+        ("a", register.get_a() as u64, number(&state["a"])),
+        ("x", register.get_x() as u64, number(&state["x"])),
+        ("y", register.get_y() as u64, number(&state["y"])),
+        (
+            "p",
+            (register.get_p() & FLAG_MASK) as u64,
+            number(&state["p"]) & FLAG_MASK as u64,
+        ),
+    ];
+    for (name, actual, expected) in registers {
+        if actual != expected {
+            return Err(format!("{} is {:X}, expected {:X}", name, actual, expected));
+        }
+    }
+    for cell in state["ram"].as_array().expect("ram") {
+        let address = number(&cell[0]) as usize;
+        let expected = number(&cell[1]) as Byte;
+        let actual = cpu.bus().memory[address];
+        if actual != expected {
+            return Err(format!(
+                "${:04X} is {:02X}, expected {:02X}",
+                address, actual, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn compare_cycles(log: &[(Word, Byte, Access)], cycles: &[Value]) -> Result<(), String> {
+    for (i, cycle) in cycles.iter().enumerate() {
+        let access = match cycle[2].as_str() {
+            Some("read") => Access::Read,
+            _ => Access::Write,
+        };
+        let expected = (number(&cycle[0]) as Word, number(&cycle[1]) as Byte, access);
+        match log.get(i) {
+            Some(actual) if *actual == expected => {}
+            actual => {
+                return Err(format!(
+                    "cycle {} was {:?}, expected {:?}",
+                    i + 1,
+                    actual,
+                    expected
+                ))
+            }
+        }
+    }
+    if log.len() > cycles.len() {
+        return Err(format!(
+            "took {} cycles, expected {}",
+            log.len(),
+            cycles.len()
+        ));
+    }
+    Ok(())
+}
+
+fn run_vector(vector: &Value) -> Result<(), String> {
+    let mut cpu = prepare_cpu(FlatBus::new());
+    load_state(&mut cpu, &vector["initial"]);
+    cpu.bus_mut().is_logging = true;
+    cpu.run();
+    compare_state(&cpu, &vector["final"])?;
+    compare_cycles(&cpu.bus().log, vector["cycles"].as_array().expect("cycles"))
+}
+
+// Returns one line per vector that diverges.
+fn run_vectors(vectors: &[Value]) -> Vec<String> {
+    vectors
+        .iter()
+        .filter_map(|vector| {
+            run_vector(vector)
+                .err()
+                .map(|error| format!("[{}] {}", vector["name"], error))
+        })
+        .collect()
+}
+
+#[test]
+#[ignore = "needs the vectors in tests/data/single_step, see the top of this file"]
+fn test_single_step() {
+    let mut report = Vec::new();
+    for byte in 0x00..=0xFF {
+        let opcode = get_opcode(byte);
+        // a jammed CPU never reaches the next instruction boundary
+        if opcode.base_name == OpcodeBaseName::JAM {
+            continue;
+        }
+        let path = format!("{}/{:02x}.json", DIRECTORY, byte);
+        let text =
+            std::fs::read_to_string(&path).unwrap_or_else(|error| panic!("{}: {}", path, error));
+        let vectors: Vec<Value> = serde_json::from_str(&text).expect(&path);
+        let failures = run_vectors(&vectors);
+        if let Some(first) = failures.first() {
+            report.push(format!(
+                "{:02X} {:?} {:?}: {}/{} failed, first {}",
+                byte,
+                opcode.base_name,
+                opcode.addressing,
+                failures.len(),
+                vectors.len(),
+                first
+            ));
+        }
+    }
+    assert!(report.is_empty(), "\n{}", report.join("\n"));
+}
+
+#[test]
+fn test_harness() {
+    // LDA $12FF,X crossing into $1300, then INC $10 writing twice
+    let vectors: Vec<Value> = serde_json::from_str(
+        r#"[
+            {
+                "name": "bd ff 12",
+                "initial": {"pc": 512, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+                    "ram": [[512, 189], [513, 255], [514, 18], [4864, 66]]},
+                "final": {"pc": 515, "s": 253, "a": 66, "x": 1, "y": 0, "p": 36,
+                    "ram": [[512, 189], [513, 255], [514, 18], [4864, 66]]},
+                "cycles": [[512, 189, "read"], [513, 255, "read"], [514, 18, "read"],
+                    [4608, 0, "read"], [4864, 66, "read"]]
+            },
+            {
+                "name": "e6 10",
+                "initial": {"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+                    "ram": [[512, 230], [513, 16], [16, 127]]},
+                "final": {"pc": 514, "s": 253, "a": 0, "x": 0, "y": 0, "p": 164,
+                    "ram": [[512, 230], [513, 16], [16, 128]]},
+                "cycles": [[512, 230, "read"], [513, 16, "read"], [16, 127, "read"],
+                    [16, 127, "write"], [16, 128, "write"]]
+            }
+        ]"#,
+    )
+    .unwrap();
+    assert!(run_vectors(&vectors).is_empty());
+
+    // a wrong final state is reported with the vector name
+    let mut vectors = vectors;
+    vectors[1]["final"]["ram"][2][1] = Value::from(129);
+    let failures = run_vectors(&vectors);
+    assert_eq!(failures, vec!["[\"e6 10\"] $0010 is 80, expected 81"]);
+}